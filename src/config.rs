@@ -1,3 +1,6 @@
+#[cfg(feature = "login-log-file")]
+use crate::webpage::auth::login_log::LoginLogConfig;
+use crate::webpage::{auth::SessionConfig, cors::CorsConfig};
 use google_youtube3::yup_oauth2::ApplicationSecret;
 use serde::{Deserialize, Serialize};
 use std::{fs, sync::LazyLock};
@@ -12,6 +15,13 @@ pub struct Config {
     pub yt_chat_viewer: ApplicationSecret,
     pub dcyt_link: ApplicationSecret,
     pub slash_command_strings: SlashCommandStrings,
+    #[serde(default)]
+    pub session: SessionConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[cfg(feature = "login-log-file")]
+    #[serde(default)]
+    pub login_log: LoginLogConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,394 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction};
+use sea_query::{Alias, Expr, Func, IdenStatic, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "login_history")]
+pub struct LoginEvent {
+    #[serde(default)]
+    pub id: i64,
+    pub sub: String,
+    pub provider: String,
+    pub ip: String,
+    #[serde(default)]
+    pub user_agent: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for LoginEvent {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(LoginEventIden::Id.as_str())?,
+            sub: value.get(LoginEventIden::Sub.as_str())?,
+            provider: value.get(LoginEventIden::Provider.as_str())?,
+            ip: value.get(LoginEventIden::Ip.as_str())?,
+            user_agent: value.get(LoginEventIden::UserAgent.as_str())?,
+            created_at: value.get(LoginEventIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl LoginEvent {
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(LoginEventIden::Table)
+            .columns([
+                LoginEventIden::Sub,
+                LoginEventIden::Provider,
+                LoginEventIden::Ip,
+                LoginEventIden::UserAgent,
+                LoginEventIden::CreatedAt,
+            ])
+            .values([
+                self.sub.clone().into(),
+                self.provider.clone().into(),
+                self.ip.clone().into(),
+                self.user_agent.clone().into(),
+                self.created_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+        transaction.execute(&query, &*values.as_params())?;
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    /// events newest first, optionally only those after `since` and those
+    /// older than the `before` id (for pagination).
+    pub fn page(
+        since: Option<DateTime<Utc>>,
+        before: Option<i64>,
+        limit: u64,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, ServerError> {
+        Self::page_where(None, since, before, limit, transaction)
+    }
+
+    /// like [`LoginEvent::page`], but only the events of the user
+    /// `(provider, sub)`.
+    pub fn page_of(
+        (provider, sub): (&str, &str),
+        since: Option<DateTime<Utc>>,
+        before: Option<i64>,
+        limit: u64,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, ServerError> {
+        Self::page_where(Some((provider, sub)), since, before, limit, transaction)
+    }
+
+    fn page_where(
+        user: Option<(&str, &str)>,
+        since: Option<DateTime<Utc>>,
+        before: Option<i64>,
+        limit: u64,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, ServerError> {
+        let mut query = Query::select();
+        query
+            .columns([
+                LoginEventIden::Id,
+                LoginEventIden::Sub,
+                LoginEventIden::Provider,
+                LoginEventIden::Ip,
+                LoginEventIden::UserAgent,
+                LoginEventIden::CreatedAt,
+            ])
+            .from(LoginEventIden::Table)
+            .order_by(LoginEventIden::Id, Order::Desc)
+            .limit(limit);
+        if let Some(since) = since {
+            query.and_where(Expr::col(LoginEventIden::CreatedAt).gte(since));
+        }
+        if let Some(before) = before {
+            query.and_where(Expr::col(LoginEventIden::Id).lt(before));
+        }
+        if let Some((provider, sub)) = user {
+            query
+                .and_where(Expr::col(LoginEventIden::Provider).eq(provider))
+                .and_where(Expr::col(LoginEventIden::Sub).eq(sub));
+        }
+        let (query, values) = query.build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let events = statement
+            .query_and_then(&*values.as_params(), |row| LoginEvent::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events)
+    }
+
+    /// the most recent event of `sub`, if any.
+    pub fn last(sub: &str, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns([
+                LoginEventIden::Id,
+                LoginEventIden::Sub,
+                LoginEventIden::Provider,
+                LoginEventIden::Ip,
+                LoginEventIden::UserAgent,
+                LoginEventIden::CreatedAt,
+            ])
+            .from(LoginEventIden::Table)
+            .and_where(Expr::col(LoginEventIden::Sub).eq(sub))
+            .order_by(LoginEventIden::Id, Order::Desc)
+            .limit(1)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let mut events = statement
+            .query_and_then(&*values.as_params(), |row| LoginEvent::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(events.pop())
+    }
+
+    /// how many users logged in, optionally only since `since`. A user is
+    /// the `(provider, sub)` pair, the same `sub` from another provider is
+    /// someone else.
+    pub fn unique_users(
+        since: Option<DateTime<Utc>>,
+        transaction: &Transaction,
+    ) -> Result<u64, ServerError> {
+        let mut users = Query::select();
+        users
+            .distinct()
+            .columns([LoginEventIden::Provider, LoginEventIden::Sub])
+            .from(LoginEventIden::Table);
+        if let Some(since) = since {
+            users.and_where(Expr::col(LoginEventIden::CreatedAt).gte(since));
+        }
+        let (query, values) = Query::select()
+            .expr(Func::count(Expr::cust("*")))
+            .from_subquery(users, Alias::new("users"))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let count = transaction.query_row(&query, &*values.as_params(), |row| row.get(0))?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use chrono::TimeDelta;
+    use rusqlite::Connection;
+
+    #[test]
+    fn insert() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+
+        let now = Utc::now();
+        let tran = conn.transaction()?;
+        let mut e0 = LoginEvent {
+            id: 0,
+            sub: String::from("test_user"),
+            provider: String::from("password"),
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            created_at: now,
+        };
+        e0.insert(&tran)?;
+        let mut e1 = LoginEvent {
+            ip: String::from("::1"),
+            ..e0.clone()
+        };
+        e1.insert(&tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let events = LoginEvent::page(None, None, 10, &tran)?;
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].id, e1.id);
+        assert_eq!(events[0].ip, "::1");
+        assert_eq!(events[1].id, e0.id);
+        assert_eq!(events[1].sub, "test_user");
+        assert_eq!(events[1].provider, "password");
+        assert_eq!(events[1].ip, "127.0.0.1");
+        assert_eq!(events[1].user_agent, "test");
+        assert_eq!(events[1].created_at, now);
+
+        assert_eq!(LoginEvent::page(None, None, 1, &tran)?.len(), 1);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn page() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+
+        let now = Utc::now();
+        let tran = conn.transaction()?;
+        let mut ids = vec![];
+        for days in (0..5).rev() {
+            let mut e = LoginEvent {
+                id: 0,
+                sub: String::from("test_user"),
+                provider: String::from("password"),
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                created_at: now - TimeDelta::days(days),
+            };
+            e.insert(&tran)?;
+            ids.push(e.id);
+        }
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let p0 = LoginEvent::page(None, None, 2, &tran)?;
+        assert_eq!(
+            p0.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [ids[4], ids[3]]
+        );
+        let p1 = LoginEvent::page(None, Some(p0[1].id), 2, &tran)?;
+        assert_eq!(
+            p1.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [ids[2], ids[1]]
+        );
+        let p2 = LoginEvent::page(None, Some(p1[1].id), 2, &tran)?;
+        assert_eq!(p2.iter().map(|e| e.id).collect::<Vec<_>>(), [ids[0]]);
+
+        let recent = LoginEvent::page(Some(now - TimeDelta::hours(36)), None, 10, &tran)?;
+        assert_eq!(
+            recent.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [ids[4], ids[3]]
+        );
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn last() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        assert!(LoginEvent::last("a", &tran)?.is_none());
+        let mut event = LoginEvent {
+            id: 0,
+            sub: String::from("a"),
+            provider: String::from("password"),
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            created_at: Utc::now(),
+        };
+        event.insert(&tran)?;
+        let mut other = LoginEvent {
+            sub: String::from("b"),
+            ..event.clone()
+        };
+        other.insert(&tran)?;
+        let mut latest = LoginEvent {
+            ip: String::from("::1"),
+            ..event.clone()
+        };
+        latest.insert(&tran)?;
+
+        let last = LoginEvent::last("a", &tran)?.unwrap();
+        assert_eq!(last.id, latest.id);
+        assert_eq!(last.ip, "::1");
+        assert_eq!(LoginEvent::last("b", &tran)?.unwrap().id, other.id);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unique_users() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+
+        let now = Utc::now();
+        let tran = conn.transaction()?;
+        assert_eq!(LoginEvent::unique_users(None, &tran)?, 0);
+        let mut event = LoginEvent {
+            id: 0,
+            sub: String::from("a"),
+            provider: String::from("password"),
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            created_at: now - TimeDelta::days(2),
+        };
+        event.insert(&tran)?;
+        // the same user again, from elsewhere
+        LoginEvent {
+            ip: String::from("::1"),
+            created_at: now,
+            ..event.clone()
+        }
+        .insert(&tran)?;
+        assert_eq!(LoginEvent::unique_users(None, &tran)?, 1);
+
+        // the same sub from another provider is another user
+        LoginEvent {
+            provider: String::from("other"),
+            ..event.clone()
+        }
+        .insert(&tran)?;
+        assert_eq!(LoginEvent::unique_users(None, &tran)?, 2);
+        assert_eq!(
+            LoginEvent::unique_users(Some(now - TimeDelta::days(1)), &tran)?,
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn page_of() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let mut mine = vec![];
+        for (provider, sub) in [
+            ("password", "a"),
+            ("password", "b"),
+            ("other", "a"),
+            ("password", "a"),
+            ("password", "a"),
+        ] {
+            let mut event = LoginEvent {
+                id: 0,
+                sub: sub.to_string(),
+                provider: provider.to_string(),
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                created_at: Utc::now(),
+            };
+            event.insert(&tran)?;
+            if (provider, sub) == ("password", "a") {
+                mine.push(event.id);
+            }
+        }
+
+        let events = LoginEvent::page_of(("password", "a"), None, None, 10, &tran)?;
+        assert_eq!(
+            events.iter().map(|e| e.id).collect::<Vec<_>>(),
+            [mine[2], mine[1], mine[0]]
+        );
+        let older = LoginEvent::page_of(("password", "a"), None, Some(mine[1]), 10, &tran)?;
+        assert_eq!(older.iter().map(|e| e.id).collect::<Vec<_>>(), [mine[0]]);
+        assert!(LoginEvent::page_of(("password", "c"), None, None, 10, &tran)?.is_empty());
+
+        Ok(())
+    }
+}
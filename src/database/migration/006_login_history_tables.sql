@@ -0,0 +1,9 @@
+CREATE TABLE `login_history` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `sub` TEXT NOT NULL,
+    `provider` TEXT NOT NULL,
+    `ip` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `login_history_i0` ON `login_history` (`created_at`);
@@ -0,0 +1 @@
+CREATE INDEX `login_history_i1` ON `login_history` (`sub`, `id`);
@@ -0,0 +1 @@
+ALTER TABLE `login_history` ADD `user_agent` TEXT NOT NULL DEFAULT 'unknown';
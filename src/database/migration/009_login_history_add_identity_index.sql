@@ -0,0 +1 @@
+CREATE INDEX `login_history_i2` ON `login_history` (`provider`, `sub`);
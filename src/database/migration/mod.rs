@@ -1,6 +1,6 @@
 use crate::error::ServerError;
 
-const VERSION: u32 = 5;
+const VERSION: u32 = 9;
 
 pub fn run_migration(transaction: &rusqlite::Transaction) -> Result<(), ServerError> {
     let mut version =
@@ -30,6 +30,10 @@ pub fn run_migration(transaction: &rusqlite::Transaction) -> Result<(), ServerEr
     migrate!(3, "003_rename_user.sql");
     migrate!(4, "004_coin_add_col.sql");
     migrate!(5, "005_coin_add_col_discord_id.sql");
+    migrate!(6, "006_login_history_tables.sql");
+    migrate!(7, "007_login_history_add_sub_index.sql");
+    migrate!(8, "008_login_history_add_col_user_agent.sql");
+    migrate!(9, "009_login_history_add_identity_index.sql");
 
     if version != VERSION {
         Err(format!(
@@ -1,4 +1,5 @@
 pub(crate) mod coin;
+pub(crate) mod login_history;
 mod migration;
 pub(crate) mod wheel;
 
@@ -6,11 +7,42 @@ use crate::error::ServerError;
 use rusqlite::Connection;
 use std::fs;
 
+#[cfg(not(test))]
 pub(crate) fn get_connection() -> Result<Connection, rusqlite::Error> {
     const DATABASE: &str = "data/sqlite.db";
     Connection::open(DATABASE)
 }
 
+#[cfg(test)]
+thread_local! {
+    /// each test thread has a database of its own, which lives in memory for
+    /// as long as a connection to it is open
+    static DATABASE: String = format!(
+        "file:{}?mode=memory&cache=shared",
+        rand::distributions::DistString::sample_string(
+            &rand::distributions::Alphanumeric,
+            &mut rand::thread_rng(),
+            16
+        )
+    );
+}
+
+#[cfg(test)]
+pub(crate) fn get_connection() -> Result<Connection, rusqlite::Error> {
+    DATABASE.with(|database| Connection::open(database))
+}
+
+/// a migrated database for handlers under test; keep the connection open
+/// while they use it.
+#[cfg(test)]
+pub(crate) fn test_database() -> Result<Connection, ServerError> {
+    let mut conn = get_connection()?;
+    let tran = conn.transaction()?;
+    migration::run_migration(&tran)?;
+    tran.commit()?;
+    Ok(conn)
+}
+
 pub fn init() -> Result<(), ServerError> {
     fs::create_dir_all("data/")?;
 
@@ -1,13 +1,17 @@
-use actix_web::ResponseError;
+use crate::webpage::request_id;
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
 use derive_more::{Display, From};
 use google_youtube3::yup_oauth2;
+use serde_json::json;
 
 #[derive(Debug, Display, From)]
 pub enum ServerError {
     SystemTime(std::time::SystemTimeError),
     Io(std::io::Error),
     Jwt(jwt::Error),
+    Openssl(openssl::error::ErrorStack),
     Json(serde_json::Error),
+    MessagePack(rmp_serde::encode::Error),
     Rusqlite(rusqlite::Error),
     SeaQuery(sea_query::error::Error),
     Serenity(serenity::Error),
@@ -15,6 +19,131 @@ pub enum ServerError {
     Google(google_youtube3::Error),
     Reqwest(reqwest::Error),
     Internal(String),
+    #[display("missing bearer token")]
+    MissingToken,
+    #[display("malformed authorization header")]
+    MalformedToken,
+    #[display("invalid or expired session")]
+    InvalidToken,
+    #[display("session expired")]
+    ExpiredToken,
+    #[display("invalid credential")]
+    Unauthorized,
+    #[display("insufficient permission")]
+    Forbidden,
+    #[from(ignore)]
+    BadRequest(String),
+    /// a json body that does not match what the endpoint expects
+    #[from(ignore)]
+    InvalidBody(String),
+    #[from(ignore)]
+    Upstream(String),
 }
 
-impl ResponseError for ServerError {}
+impl ServerError {
+    /// machine-readable code, stable for the frontend to switch on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ServerError::MissingToken => "missing_token",
+            ServerError::MalformedToken => "malformed_token",
+            ServerError::InvalidToken => "invalid_token",
+            ServerError::ExpiredToken => "expired_token",
+            ServerError::Unauthorized => "invalid_credential",
+            ServerError::Forbidden => "forbidden",
+            ServerError::BadRequest(_) => "bad_request",
+            ServerError::InvalidBody(_) => "invalid_request_body",
+            ServerError::Upstream(_) | ServerError::Reqwest(_) => "upstream",
+            _ => "internal",
+        }
+    }
+}
+
+impl ResponseError for ServerError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ServerError::MissingToken
+            | ServerError::MalformedToken
+            | ServerError::InvalidToken
+            | ServerError::ExpiredToken
+            | ServerError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ServerError::Forbidden => StatusCode::FORBIDDEN,
+            ServerError::BadRequest(_) | ServerError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+            ServerError::Upstream(_) | ServerError::Reqwest(_) => StatusCode::BAD_GATEWAY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        // internal details stay in the log
+        let message = if status == StatusCode::INTERNAL_SERVER_ERROR {
+            log::error!(
+                "internal server error in request {}: {:?}",
+                request_id::current().as_deref().unwrap_or("unknown"),
+                self
+            );
+            String::from("internal server error")
+        } else {
+            self.to_string()
+        };
+
+        HttpResponse::build(status).json(json!({
+            "error": self.code(),
+            "message": message,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::Value;
+
+    async fn render(err: ServerError) -> (StatusCode, Value) {
+        let response = err.error_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn error_response_is_structured() {
+        let cases = [
+            (ServerError::MissingToken, 401, "missing_token"),
+            (ServerError::MalformedToken, 401, "malformed_token"),
+            (ServerError::InvalidToken, 401, "invalid_token"),
+            (ServerError::ExpiredToken, 401, "expired_token"),
+            (ServerError::Unauthorized, 401, "invalid_credential"),
+            (ServerError::Forbidden, 403, "forbidden"),
+            (
+                ServerError::BadRequest(String::from("x")),
+                400,
+                "bad_request",
+            ),
+            (
+                ServerError::InvalidBody(String::from("x")),
+                400,
+                "invalid_request_body",
+            ),
+            (ServerError::Upstream(String::from("x")), 502, "upstream"),
+            (ServerError::Internal(String::from("x")), 500, "internal"),
+        ];
+
+        for (err, status, code) in cases {
+            let (actual, body) = render(err).await;
+            assert_eq!(actual.as_u16(), status);
+            assert_eq!(body["error"], code);
+            assert!(body["message"].is_string());
+        }
+    }
+
+    #[actix_web::test]
+    async fn internal_error_hides_details() {
+        let (_, body) = render(ServerError::Internal(String::from("secret path"))).await;
+        assert_eq!(body["message"], "internal server error");
+
+        let (_, body) = render(ServerError::BadRequest(String::from("missing field"))).await;
+        assert_eq!(body["message"], "missing field");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    database::{self, login_history::LoginEvent},
+    error::ServerError,
+    webpage::auth::{Admin, RequireRole},
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_LIMIT: u64 = 50;
+const MAX_LIMIT: u64 = 500;
+
+/// a page of login history, shared with the user's own listing.
+#[derive(Debug, Deserialize)]
+pub(crate) struct Request {
+    limit: Option<u64>,
+    pub since: Option<DateTime<Utc>>,
+    pub cursor: Option<i64>,
+}
+
+impl Request {
+    pub fn limit(&self) -> u64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Response {
+    events: Vec<LoginEvent>,
+    next_cursor: Option<i64>,
+}
+
+/// `events` is expected to hold up to `limit + 1` entries, the extra one
+/// only signals that another page exists.
+pub(crate) fn paginate(mut events: Vec<LoginEvent>, limit: u64) -> Response {
+    let next_cursor = if events.len() as u64 > limit {
+        events.truncate(limit as usize);
+        events.last().map(|e| e.id)
+    } else {
+        None
+    };
+    Response {
+        events,
+        next_cursor,
+    }
+}
+
+#[get("/logins")]
+pub async fn handler(
+    _: RequireRole<Admin>,
+    request: web::Query<Request>,
+) -> Result<impl Responder, ServerError> {
+    let limit = request.limit();
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let events = LoginEvent::page(request.since, request.cursor, limit + 1, &transaction)?;
+
+    Ok(HttpResponse::Ok().json(paginate(events, limit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::{
+        admin,
+        auth::{SessionConfig, revocation::RevocationStore, test_clock, test_token},
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service},
+    };
+
+    fn event(id: i64) -> LoginEvent {
+        LoginEvent {
+            id,
+            sub: String::from("user"),
+            provider: String::from("password"),
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn paginate_reports_next_cursor() {
+        let response = paginate((1..=3).rev().map(event).collect(), 2);
+        assert_eq!(response.events.len(), 2);
+        assert_eq!(response.next_cursor, Some(2));
+
+        let response = paginate((1..=2).rev().map(event).collect(), 2);
+        assert_eq!(response.events.len(), 2);
+        assert_eq!(response.next_cursor, None);
+    }
+
+    #[actix_web::test]
+    async fn non_admin_is_forbidden() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(admin::configure),
+        )
+        .await;
+
+        let request = TestRequest::get()
+            .uri("/api/admin/logins")
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", test_token("user")),
+            ))
+            .to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::FORBIDDEN
+        );
+
+        let request = TestRequest::get().uri("/api/admin/logins").to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // mounted like the auth endpoints, so never cached either
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-store"
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::{
+    error::ServerError,
+    webpage::auth::{self, Admin, RequireRole, tracker::SessionTracker},
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Response {
+    /// sessions that were still tracked
+    ended: usize,
+}
+
+/// log everyone out, the admin included, by replacing every session key,
+/// e.g. after a breach. Unlike a rotation, no old token stays valid.
+#[post("/logout-all")]
+pub async fn handler(
+    admin: RequireRole<Admin>,
+    tracker: web::Data<SessionTracker>,
+) -> Result<impl Responder, ServerError> {
+    auth::force_logout_all()?;
+    let ended = tracker.clear();
+    log::warn!("{} logged out all {} sessions", admin.0.sub, ended);
+
+    Ok(HttpResponse::Ok().json(Response { ended }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::{
+        admin,
+        auth::{SessionConfig, revocation::RevocationStore, test_clock, test_token},
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service},
+    };
+
+    // the shared key ring is not replaced here, that would log out every
+    // other test; `KeyRing::replace_all` is covered in `auth::keys`
+    #[actix_web::test]
+    async fn non_admin_is_forbidden() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(web::Data::new(SessionTracker::default()))
+                .app_data(test_clock())
+                .configure(admin::configure),
+        )
+        .await;
+        let token = test_token("user");
+
+        let request = TestRequest::post()
+            .uri("/api/admin/logout-all")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+}
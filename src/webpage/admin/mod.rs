@@ -0,0 +1,20 @@
+use super::auth::{inflight, json_config, no_store};
+use actix_web::{middleware, web};
+
+pub mod logins;
+pub mod logout_all;
+pub mod revoke;
+
+/// mount the admin endpoints under `/api/admin`, behind the same guards and
+/// headers as the auth endpoints.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/admin")
+            .wrap(middleware::from_fn(inflight::guard))
+            .wrap(no_store())
+            .app_data(json_config())
+            .service(logins::handler)
+            .service(logout_all::handler)
+            .service(revoke::handler),
+    );
+}
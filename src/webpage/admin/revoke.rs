@@ -0,0 +1,123 @@
+use crate::{
+    error::ServerError,
+    webpage::auth::{
+        Admin, RequireRole, clock::Clock, revocation::RevocationStore, tracker::SessionTracker,
+    },
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    sub: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Response {
+    /// sessions of the user that were still tracked
+    revoked: usize,
+}
+
+/// log a user out everywhere, like a logout with `all` on their behalf.
+#[post("/revoke")]
+pub async fn handler(
+    admin: RequireRole<Admin>,
+    request: web::Json<Request>,
+    revocations: web::Data<RevocationStore>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+) -> Result<impl Responder, ServerError> {
+    let now = clock.now_secs();
+    revocations.revoke_all(&request.sub, now);
+    let revoked = tracker.end_all(&request.sub);
+    log::warn!(
+        "{} revoked {} sessions of {}",
+        admin.0.sub,
+        revoked,
+        request.sub
+    );
+
+    Ok(HttpResponse::Ok().json(Response { revoked }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::{
+        admin,
+        auth::{
+            SessionConfig, TEST_NOW, configure, test_clock, test_token, test_token_with,
+            tracker::SessionInfo,
+        },
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+
+    #[actix_web::test]
+    async fn revoked_user_can_no_longer_tick() {
+        let config = SessionConfig {
+            roles: HashMap::from([(String::from("admin"), vec![String::from("admin")])]),
+            ..Default::default()
+        };
+        let admin = test_token_with("admin", &config);
+        let token = test_token("user");
+        let tracker = web::Data::new(SessionTracker::default());
+        tracker.start(
+            "sid",
+            SessionInfo {
+                sub: String::from("user"),
+                issued_at: TEST_NOW - 1,
+                last_active: TEST_NOW - 1,
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                jti: String::new(),
+                expires_at: TEST_NOW + 3600,
+            },
+            TEST_NOW - 1,
+        );
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(tracker)
+                .configure(admin::configure)
+                .configure(configure),
+        )
+        .await;
+        let revoke = |bearer: &str| {
+            TestRequest::post()
+                .uri("/api/admin/revoke")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", bearer)))
+                .set_json(json!({ "sub": "user" }))
+                .to_request()
+        };
+
+        let response = call_service(&app, revoke(&token)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = call_service(&app, revoke(&admin)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert_eq!(body, json!({ "revoked": 1 }));
+
+        let request = TestRequest::post()
+            .uri("/api/auth/tick")
+            .set_json(json!({ "token": token }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        // the admin's own session is untouched
+        let request = TestRequest::get()
+            .uri("/api/auth/session")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin)))
+            .to_request();
+        assert_eq!(call_service(&app, request).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,176 @@
+use super::{
+    Admin, RequireRole, SessionConfig, TokenType, clock::Clock, json_error,
+    revocation::RevocationStore, verify,
+};
+use crate::error::ServerError;
+use actix_web::{HttpResponse, Resource, Responder, web};
+use serde::Serialize;
+
+const MAX_BATCH: usize = 100;
+
+/// room for a full batch of the longest tokens we issue, RS256 with a 4096
+/// bit key and every optional claim
+const MAX_TOKEN_LEN: usize = 2 * 1024;
+
+#[derive(Debug, Serialize)]
+struct Verdict {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub: Option<String>,
+}
+
+/// `/verify-batch`, whose body is allowed to outgrow the other auth endpoints'.
+pub fn service() -> Resource {
+    web::resource("/verify-batch")
+        .app_data(
+            web::JsonConfig::default()
+                .limit(MAX_BATCH * MAX_TOKEN_LEN)
+                .error_handler(json_error),
+        )
+        .route(web::post().to(handler))
+}
+
+/// check many access tokens at once, for other services.
+async fn handler(
+    _: RequireRole<Admin>,
+    tokens: web::Json<Vec<String>>,
+    config: web::Data<SessionConfig>,
+    revocations: web::Data<RevocationStore>,
+    clock: web::Data<dyn Clock>,
+) -> Result<impl Responder, ServerError> {
+    if tokens.len() > MAX_BATCH {
+        return Err(ServerError::BadRequest(format!(
+            "at most {} tokens per batch",
+            MAX_BATCH
+        )));
+    }
+
+    let now = clock.now_secs();
+    let verdicts: Vec<_> = tokens
+        .iter()
+        .map(
+            |token| match verify(token, TokenType::Access, now, &config, &revocations) {
+                Some(claims) => Verdict {
+                    valid: true,
+                    sub: Some(claims.sub),
+                },
+                None => Verdict {
+                    valid: false,
+                    sub: None,
+                },
+            },
+        )
+        .collect();
+
+    Ok(HttpResponse::Ok().json(verdicts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        Claims, JSON_LIMIT, TEST_NOW, configure, issue_token, test_clock, test_token,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+    use serde_json::{Value, json};
+    use std::collections::HashMap;
+
+    async fn verify_batch(tokens: Value) -> (StatusCode, Value) {
+        let config = SessionConfig {
+            roles: HashMap::from([(String::from("admin"), vec![String::from("admin")])]),
+            ..Default::default()
+        };
+        let now = TEST_NOW;
+        let admin = issue_token(&Claims::new("admin", now - 1, &config)).unwrap();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+
+        let request = TestRequest::post()
+            .uri("/api/auth/verify-batch")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", admin)))
+            .set_json(tokens)
+            .to_request();
+        let response = call_service(&app, request).await;
+        let status = response.status();
+        let body = read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn mixed_batch_is_judged_per_token() {
+        let expired = issue_token(&Claims::new("user", 100, &SessionConfig::default())).unwrap();
+        let (status, body) = verify_batch(json!([test_token("user"), expired, "garbage"])).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body,
+            json!([
+                { "valid": true, "sub": "user" },
+                { "valid": false },
+                { "valid": false },
+            ])
+        );
+    }
+
+    #[actix_web::test]
+    async fn full_batch_of_real_tokens_is_accepted() {
+        let mut tokens: Vec<_> = (0..MAX_BATCH).map(|_| test_token("user")).collect();
+        // pad the claims the way a remembered, bound login would
+        tokens[0] = issue_token(&Claims {
+            provider: String::from("google"),
+            uah: Some("x".repeat(43)),
+            remember: true,
+            ..Claims::new("user", TEST_NOW - 1, &SessionConfig::default())
+        })
+        .unwrap();
+        assert!(serde_json::to_vec(&tokens).unwrap().len() > JSON_LIMIT);
+
+        let (status, body) = verify_batch(json!(tokens)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.as_array().unwrap().len(), MAX_BATCH);
+        assert!(body.as_array().unwrap().iter().all(|v| v["valid"] == true));
+    }
+
+    #[actix_web::test]
+    async fn oversized_batch_is_rejected() {
+        let tokens = vec!["garbage"; MAX_BATCH + 1];
+        let (status, body) = verify_batch(json!(tokens)).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "bad_request");
+    }
+
+    #[actix_web::test]
+    async fn non_admin_is_forbidden() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+        let request = TestRequest::post()
+            .uri("/api/auth/verify-batch")
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", test_token("user")),
+            ))
+            .set_json(json!([]))
+            .to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+}
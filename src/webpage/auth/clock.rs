@@ -0,0 +1,98 @@
+use actix_web::web;
+#[cfg(test)]
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// source of the current unix time in seconds, injected so tests control it.
+pub trait Clock: Send + Sync {
+    fn now_secs(&self) -> u64;
+}
+
+/// seconds from the epoch to `time`. A time before the epoch only comes
+/// from a misconfigured system clock, it is logged and clamped to the epoch.
+fn unix_secs(time: SystemTime) -> u64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs(),
+        Err(err) => {
+            log::error!(
+                "system clock is {}s before the unix epoch, check the host time",
+                err.duration().as_secs()
+            );
+            0
+        }
+    }
+}
+
+/// the current unix time in seconds, for code without a [`Clock`] at hand.
+pub fn current_unix_secs() -> u64 {
+    unix_secs(SystemTime::now())
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        current_unix_secs()
+    }
+}
+
+/// a clock that only moves when told to.
+#[cfg(test)]
+#[derive(Debug)]
+pub struct MockClock(AtomicU64);
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new(now: u64) -> Self {
+        Self(AtomicU64::new(now))
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.0.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now_secs(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// wrap `clock` for `App::app_data`, handlers extract it as `web::Data<dyn Clock>`.
+pub fn data(clock: Arc<dyn Clock>) -> web::Data<dyn Clock> {
+    web::Data::from(clock)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_advances() {
+        let clock = MockClock::new(100);
+        clock.advance(50);
+        assert_eq!(clock.now_secs(), 150);
+    }
+
+    #[test]
+    fn system_clock_is_current() {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        assert!(SystemClock.now_secs().abs_diff(now) <= 1);
+    }
+
+    #[test]
+    fn time_before_epoch_is_clamped() {
+        use std::time::Duration;
+
+        assert_eq!(unix_secs(UNIX_EPOCH - Duration::from_secs(60)), 0);
+        assert_eq!(unix_secs(UNIX_EPOCH + Duration::from_secs(60)), 60);
+    }
+}
@@ -0,0 +1,124 @@
+use super::{Claims, SessionConfig, prune::Prunable};
+use std::{collections::HashMap, sync::Mutex};
+
+/// a session handed out moments ago, with the tokens it was handed out as.
+#[derive(Debug, Clone)]
+pub struct Recent {
+    pub claims: Claims,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+/// sessions just granted per `(provider, sub, remember)`, so a double-clicked
+/// login gets the session of the first click instead of a second one.
+#[derive(Debug, Default)]
+pub struct LoginDebounce {
+    recent: Mutex<HashMap<(String, String, bool), Recent>>,
+}
+
+impl LoginDebounce {
+    /// the session granted to `(provider, sub)` within the debounce window
+    /// for a login with the same `remember` choice.
+    pub fn recent(
+        &self,
+        provider: &str,
+        sub: &str,
+        remember: bool,
+        now: u64,
+        config: &SessionConfig,
+    ) -> Option<Recent> {
+        config.login_debounce?;
+        self.prune(now, config);
+        self.recent
+            .lock()
+            .expect("login debounce poisoned")
+            .get(&(provider.to_string(), sub.to_string(), remember))
+            .cloned()
+    }
+
+    pub fn remember(&self, recent: Recent, config: &SessionConfig) {
+        if config.login_debounce.is_none() {
+            return;
+        }
+        let key = (
+            recent.claims.provider.clone(),
+            recent.claims.sub.clone(),
+            recent.claims.remember,
+        );
+        self.recent
+            .lock()
+            .expect("login debounce poisoned")
+            .insert(key, recent);
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.recent.lock().unwrap().len()
+    }
+}
+
+impl Prunable for LoginDebounce {
+    fn prune(&self, now: u64, config: &SessionConfig) {
+        let window = config.login_debounce.unwrap_or_default().as_secs();
+        self.recent
+            .lock()
+            .expect("login debounce poisoned")
+            .retain(|_, recent| recent.claims.iat + window > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn recent(now: u64, config: &SessionConfig) -> Recent {
+        Recent {
+            claims: Claims::new("user", now, config),
+            token: String::from("token"),
+            refresh_token: String::from("refresh"),
+        }
+    }
+
+    #[test]
+    fn session_is_shared_within_window() {
+        let config = SessionConfig {
+            login_debounce: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let debounce = LoginDebounce::default();
+        debounce.remember(recent(100, &config), &config);
+
+        let shared = debounce
+            .recent("password", "user", false, 104, &config)
+            .unwrap();
+        assert_eq!(shared.token, "token");
+        assert!(
+            debounce
+                .recent("other", "user", false, 104, &config)
+                .is_none()
+        );
+        assert!(
+            debounce
+                .recent("password", "user", true, 104, &config)
+                .is_none()
+        );
+        assert!(
+            debounce
+                .recent("password", "user", false, 105, &config)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        let config = SessionConfig::default();
+        let debounce = LoginDebounce::default();
+        debounce.remember(recent(100, &config), &config);
+        assert!(
+            debounce
+                .recent("password", "user", false, 100, &config)
+                .is_none()
+        );
+    }
+}
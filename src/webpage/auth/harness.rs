@@ -0,0 +1,170 @@
+//! end-to-end tests driving the auth routes through a fully wired app, with
+//! the same shared state `webpage::run` registers.
+
+use super::{
+    SessionConfig, TEST_NOW,
+    clock::{self, MockClock},
+    configure,
+    lockout::LoginLockout,
+    revocation::RevocationStore,
+    sink::{LoginSinks, NoopSink},
+    test_token,
+    tracker::SessionTracker,
+};
+use actix_http::Request;
+use actix_web::{
+    App, Error,
+    body::MessageBody,
+    dev::{Service, ServiceResponse},
+    http::{StatusCode, header},
+    test, web,
+};
+use serde_json::{Value, json};
+use std::sync::Arc;
+
+struct Harness {
+    clock: Arc<MockClock>,
+    config: web::Data<SessionConfig>,
+    revocations: web::Data<RevocationStore>,
+    tracker: web::Data<SessionTracker>,
+    lockout: web::Data<LoginLockout>,
+    sinks: web::Data<LoginSinks>,
+}
+
+impl Harness {
+    fn new() -> Self {
+        Self {
+            clock: Arc::new(MockClock::new(TEST_NOW)),
+            config: web::Data::new(SessionConfig::default()),
+            revocations: web::Data::new(RevocationStore::default()),
+            tracker: web::Data::new(SessionTracker::default()),
+            lockout: web::Data::new(LoginLockout::default()),
+            // nothing is written to the database or the login log
+            sinks: web::Data::new(LoginSinks::default().with(NoopSink)),
+        }
+    }
+
+    /// register the shared state and every auth route; new endpoints only
+    /// need to be added to `auth::configure`.
+    fn configure(&self, cfg: &mut web::ServiceConfig) {
+        cfg.app_data(self.config.clone())
+            .app_data(self.revocations.clone())
+            .app_data(clock::data(self.clock.clone()))
+            .app_data(self.tracker.clone())
+            .app_data(self.lockout.clone())
+            .app_data(self.sinks.clone());
+        configure(cfg);
+    }
+
+    async fn app(
+        &self,
+    ) -> impl Service<Request, Response = ServiceResponse<impl MessageBody>, Error = Error> {
+        test::init_service(App::new().configure(|cfg| self.configure(cfg))).await
+    }
+}
+
+async fn send<S, B>(app: &S, request: test::TestRequest, token: Option<&str>) -> (StatusCode, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    let request = match token {
+        Some(token) => request.insert_header((header::AUTHORIZATION, format!("Bearer {}", token))),
+        None => request,
+    };
+    let response = test::call_service(app, request.to_request()).await;
+    let status = response.status();
+    let body = test::read_body(response).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
+
+async fn post<S, B>(app: &S, uri: &str, body: Value) -> (StatusCode, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    send(app, test::TestRequest::post().uri(uri).set_json(body), None).await
+}
+
+async fn get<S, B>(app: &S, uri: &str, token: &str) -> (StatusCode, Value)
+where
+    S: Service<Request, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    send(app, test::TestRequest::get().uri(uri), Some(token)).await
+}
+
+#[actix_web::test]
+async fn tick_refreshes_session() {
+    let harness = Harness::new();
+    let app = harness.app().await;
+    let token = test_token("user");
+
+    harness.clock.advance(60);
+    let (status, body) = post(&app, "/api/auth/tick", json!({ "token": token })).await;
+    assert_eq!(status, StatusCode::OK);
+    let refreshed = body["token"].as_str().unwrap();
+    assert_ne!(refreshed, token);
+    assert_eq!(body["issued_at"], TEST_NOW + 60);
+
+    let (status, body) = get(&app, "/api/auth/session", refreshed).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["sub"], "user");
+}
+
+#[actix_web::test]
+async fn logout_is_seen_by_every_route() {
+    let harness = Harness::new();
+    let app = harness.app().await;
+    let token = test_token("user");
+
+    let (status, body) = post(
+        &app,
+        "/api/auth/logout",
+        json!({ "username": "user", "token": token }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["logged_out"], true);
+
+    let (status, body) = post(&app, "/api/auth/tick", json!({ "token": token })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "invalid_session");
+    let (status, _) = get(&app, "/api/auth/session", &token).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn unknown_route_is_not_found() {
+    let harness = Harness::new();
+    let app = harness.app().await;
+
+    let (status, _) = post(&app, "/api/auth/google", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn auth_responses_are_not_cached() {
+    let harness = Harness::new();
+    let app = harness.app().await;
+    let token = test_token("user");
+
+    let requests = [json!({ "token": token }), json!({ "token": "invalid" })]
+        .map(|body| ("/api/auth/tick", body))
+        .into_iter()
+        .chain([(
+            "/api/auth/logout",
+            json!({ "username": "user", "token": token }),
+        )]);
+    for (uri, body) in requests {
+        let request = test::TestRequest::post().uri(uri).set_json(body);
+        let response = test::call_service(&app, request.to_request()).await;
+        let headers = response.headers();
+        assert_eq!(headers.get(header::CACHE_CONTROL).unwrap(), "no-store");
+        assert_eq!(headers.get(header::PRAGMA).unwrap(), "no-cache");
+        assert_eq!(
+            headers.get(header::VARY).unwrap(),
+            "Accept, Authorization, Cookie"
+        );
+    }
+}
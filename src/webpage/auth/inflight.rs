@@ -0,0 +1,109 @@
+use actix_web::{
+    Error, HttpResponse,
+    body::{BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header,
+    middleware::Next,
+    web,
+};
+use tokio::sync::Semaphore;
+
+/// how long a shed client is asked to wait before trying again, in seconds
+const RETRY_AFTER: u64 = 1;
+
+/// caps how many auth requests are processed at once, so a flood of logins
+/// is turned away instead of tying up every worker.
+#[derive(Debug)]
+pub struct InFlightLimit(Option<Semaphore>);
+
+impl InFlightLimit {
+    /// `max` requests at once, 0 for no limit.
+    pub fn new(max: usize) -> Self {
+        Self((max > 0).then(|| Semaphore::new(max)))
+    }
+}
+
+/// shed auth requests beyond the limit with a 503. Without a registered
+/// limit every request is let through.
+pub async fn guard(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let limit = request.app_data::<web::Data<InFlightLimit>>().cloned();
+    let _permit = match limit.as_deref().and_then(|limit| limit.0.as_ref()) {
+        Some(semaphore) => match semaphore.try_acquire() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                log::warn!(
+                    "shedding {}, too many auth requests in flight",
+                    request.path()
+                );
+                let response = HttpResponse::ServiceUnavailable()
+                    .insert_header((header::RETRY_AFTER, RETRY_AFTER.to_string()))
+                    .finish();
+                return Ok(request.into_response(response));
+            }
+        },
+        None => None,
+    };
+    Ok(next.call(request).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App,
+        http::StatusCode,
+        middleware::from_fn,
+        test::{TestRequest, call_service, init_service},
+    };
+    use std::rc::Rc;
+    use tokio::sync::Notify;
+
+    async fn slow(release: web::Data<Notify>) -> HttpResponse {
+        release.notified().await;
+        HttpResponse::Ok().finish()
+    }
+
+    #[actix_web::test]
+    async fn requests_beyond_limit_are_shed() {
+        let limit = web::Data::new(InFlightLimit::new(1));
+        let release = web::Data::new(Notify::new());
+        let app = Rc::new(
+            init_service(
+                App::new()
+                    .app_data(limit.clone())
+                    .app_data(release.clone())
+                    .service(
+                        web::scope("/api/auth")
+                            .wrap(from_fn(guard))
+                            .route("/slow", web::get().to(slow)),
+                    ),
+            )
+            .await,
+        );
+        let request = || TestRequest::get().uri("/api/auth/slow").to_request();
+
+        let first = actix_web::rt::spawn({
+            let app = app.clone();
+            let request = request();
+            async move { call_service(&*app, request).await.status() }
+        });
+        let semaphore = limit.0.as_ref().unwrap();
+        while semaphore.available_permits() > 0 {
+            actix_web::rt::task::yield_now().await;
+        }
+
+        let response = call_service(&*app, request()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "1");
+
+        // the earlier request is unaffected and frees its slot when done
+        release.notify_one();
+        assert_eq!(first.await.unwrap(), StatusCode::OK);
+        release.notify_one();
+        let response = call_service(&*app, request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
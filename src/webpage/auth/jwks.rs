@@ -0,0 +1,30 @@
+use super::KEYS;
+use crate::error::ServerError;
+use actix_web::{HttpResponse, Responder, get};
+
+/// the public keys session tokens are signed with, for other services to
+/// verify them. Empty when tokens are signed with a shared secret.
+#[get("/jwks")]
+pub async fn handler() -> Result<impl Responder, ServerError> {
+    let jwks = KEYS.read().expect("key ring poisoned").jwks()?;
+    Ok(HttpResponse::Ok().json(jwks))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::webpage::auth::configure;
+    use actix_web::{App, http::StatusCode, test};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn jwks_is_served() {
+        let app = test::init_service(App::new().configure(configure)).await;
+        let request = test::TestRequest::get().uri("/api/auth/jwks").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // tests sign with a shared secret, which is never exposed
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body, serde_json::json!({ "keys": [] }));
+    }
+}
@@ -0,0 +1,787 @@
+use super::Claims;
+use crate::error::ServerError;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use jwt::{
+    AlgorithmType, Header, SignWithKey, SigningAlgorithm, Token, Unverified, VerifyWithKey,
+    VerifyingAlgorithm,
+};
+use openssl::{
+    bn::{BigNum, BigNumContext, BigNumRef},
+    ecdsa::EcdsaSig,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{HasPublic, Id, PKey, PKeyRef, Private, Public},
+    sign::{Signer, Verifier},
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::{Path, PathBuf},
+};
+
+// tokens outlive a rotation by at most one session lifetime, so only a
+// handful of retired keys ever need to be kept around
+const MAX_RETIRED_KEYS: usize = 4;
+
+const SECRET_LEN: usize = 32;
+
+// the width of a P-256 coordinate, and of each half of an ES256 signature
+const P256_LEN: usize = 32;
+
+/// the algorithm session tokens are signed with. The asymmetric ones let
+/// other services verify our tokens without holding the signing secret.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "algorithm")]
+pub enum SigningConfig {
+    /// a random secret kept in the session key file
+    #[default]
+    #[serde(rename = "HS256")]
+    Hs256,
+    #[serde(rename = "RS256")]
+    Rs256 {
+        private_key: PathBuf,
+        public_key: PathBuf,
+    },
+    #[serde(rename = "ES256")]
+    Es256 {
+        private_key: PathBuf,
+        public_key: PathBuf,
+    },
+}
+
+fn kid(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+struct SigningKey {
+    kid: String,
+    secret: [u8; SECRET_LEN],
+    key: Hmac<Sha256>,
+}
+
+impl SigningKey {
+    fn new(secret: [u8; SECRET_LEN]) -> Self {
+        let kid = kid(&secret);
+        let key = Hmac::new_from_slice(&secret).expect("fail to generate HMAC key.");
+        Self { kid, secret, key }
+    }
+}
+
+/// the primary session key plus the keys it replaced, which are still
+/// accepted so that rotation does not log everyone out.
+pub struct KeyRing {
+    path: Option<PathBuf>,
+    primary: SigningKey,
+    retired: Vec<SigningKey>,
+}
+
+impl KeyRing {
+    /// a key ring which lives in memory only.
+    pub fn ephemeral() -> Self {
+        Self {
+            path: None,
+            primary: SigningKey::new(random_secret()),
+            retired: vec![],
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, ServerError> {
+        let primary = SigningKey::new(session_secret(path)?);
+        let retired = match fs::read(retired_path(path)) {
+            Ok(bytes) => bytes
+                .chunks_exact(SECRET_LEN)
+                .map(|chunk| SigningKey::new(chunk.try_into().expect("chunk has a fixed size")))
+                .collect(),
+            Err(err) if err.kind() == ErrorKind::NotFound => vec![],
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            primary,
+            retired,
+        })
+    }
+
+    pub fn sign(&self, claims: &Claims) -> Result<String, ServerError> {
+        let header = Header {
+            algorithm: AlgorithmType::Hs256,
+            key_id: Some(self.primary.kid.clone()),
+            ..Default::default()
+        };
+        let token = Token::new(header, claims).sign_with_key(&self.primary.key)?;
+        Ok(token.as_str().to_string())
+    }
+
+    /// check the signature of `token`, returning its claims when valid.
+    pub fn decode(&self, token: &str) -> Option<Claims> {
+        let unverified: Token<Header, Claims, Unverified> = Token::parse_unverified(token).ok()?;
+        let kid = unverified.header().key_id.as_deref();
+
+        let keys = || std::iter::once(&self.primary).chain(&self.retired);
+        let candidates: Vec<_> = match keys().find(|key| Some(key.kid.as_str()) == kid) {
+            Some(key) => vec![key],
+            // tokens without a known `kid` predate rotation, try everything
+            None => keys().collect(),
+        };
+
+        candidates.into_iter().find_map(|key| {
+            let token: Token<Header, Claims, _> = token.verify_with_key(&key.key).ok()?;
+            Some(token.claims().clone())
+        })
+    }
+
+    /// replace the primary key with a fresh one, retiring the current one.
+    pub fn rotate(&mut self) -> Result<(), ServerError> {
+        let primary = std::mem::replace(&mut self.primary, SigningKey::new(random_secret()));
+        self.retired.insert(0, primary);
+        self.retired.truncate(MAX_RETIRED_KEYS);
+
+        self.persist()
+    }
+
+    /// replace the primary key and forget every retired one, so that no
+    /// token issued so far verifies any more.
+    pub fn replace_all(&mut self) -> Result<(), ServerError> {
+        self.primary = SigningKey::new(random_secret());
+        self.retired.clear();
+        self.persist()
+    }
+
+    fn persist(&self) -> Result<(), ServerError> {
+        if let Some(path) = &self.path {
+            let retired: Vec<u8> = self.retired.iter().flat_map(|key| key.secret).collect();
+            write_secret(&retired_path(path), &retired)?;
+            write_secret(path, &self.primary.secret)?;
+        }
+        Ok(())
+    }
+}
+
+/// an openssl key used as a jwt algorithm. ECDSA signatures are kept as the
+/// fixed width `r || s` of rfc 7518 rather than openssl's DER.
+struct Asymmetric<T> {
+    algorithm: AlgorithmType,
+    key: PKey<T>,
+}
+
+impl SigningAlgorithm for Asymmetric<Private> {
+    fn algorithm_type(&self) -> AlgorithmType {
+        self.algorithm
+    }
+
+    fn sign(&self, header: &str, claims: &str) -> Result<String, jwt::Error> {
+        let mut signer = Signer::new(MessageDigest::sha256(), &self.key)?;
+        signer.update(format!("{}.{}", header, claims).as_bytes())?;
+        let mut signature = signer.sign_to_vec()?;
+        if self.algorithm == AlgorithmType::Es256 {
+            // r and s lose their leading zeros in DER, put them back
+            let der = EcdsaSig::from_der(&signature)?;
+            signature = der.r().to_vec_padded(P256_LEN as i32)?;
+            signature.extend(der.s().to_vec_padded(P256_LEN as i32)?);
+        }
+        Ok(URL_SAFE_NO_PAD.encode(signature))
+    }
+}
+
+impl VerifyingAlgorithm for Asymmetric<Public> {
+    fn algorithm_type(&self) -> AlgorithmType {
+        self.algorithm
+    }
+
+    fn verify_bytes(
+        &self,
+        header: &str,
+        claims: &str,
+        signature: &[u8],
+    ) -> Result<bool, jwt::Error> {
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &self.key)?;
+        verifier.update(format!("{}.{}", header, claims).as_bytes())?;
+        if self.algorithm != AlgorithmType::Es256 {
+            return Ok(verifier.verify(signature)?);
+        }
+        if signature.len() != 2 * P256_LEN {
+            return Ok(false);
+        }
+        let (r, s) = signature.split_at(P256_LEN);
+        let der =
+            EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?;
+        Ok(verifier.verify(&der.to_der()?)?)
+    }
+}
+
+/// whether `key` is the kind of key `algorithm` signs with.
+fn matches<T: HasPublic>(key: &PKeyRef<T>, algorithm: AlgorithmType) -> bool {
+    match algorithm {
+        AlgorithmType::Rs256 => key.id() == Id::RSA,
+        AlgorithmType::Es256 => key
+            .ec_key()
+            .is_ok_and(|ec| ec.group().curve_name() == Some(Nid::X9_62_PRIME256V1)),
+        _ => false,
+    }
+}
+
+/// a private key to sign with and the public key to verify against, both
+/// read from pem files.
+pub struct KeyPair {
+    kid: String,
+    private: Asymmetric<Private>,
+    public: Asymmetric<Public>,
+}
+
+impl KeyPair {
+    /// load a key pair for `algorithm`, currently RS256 or ES256 on P-256.
+    pub fn load(
+        algorithm: AlgorithmType,
+        private_key: &Path,
+        public_key: &Path,
+    ) -> Result<Self, ServerError> {
+        let private = PKey::private_key_from_pem(&fs::read(private_key)?)?;
+        let public = PKey::public_key_from_pem(&fs::read(public_key)?)?;
+        if !matches(&private, algorithm) || !matches(&public, algorithm) {
+            return Err(format!(
+                "{} and {} are not {:?} keys",
+                private_key.display(),
+                public_key.display(),
+                algorithm
+            )
+            .into());
+        }
+        if !private.public_eq(&public) {
+            return Err(format!(
+                "{} is not the public key of {}",
+                public_key.display(),
+                private_key.display()
+            )
+            .into());
+        }
+
+        Ok(Self {
+            kid: kid(&public.public_key_to_der()?),
+            private: Asymmetric {
+                algorithm,
+                key: private,
+            },
+            public: Asymmetric {
+                algorithm,
+                key: public,
+            },
+        })
+    }
+
+    fn sign(&self, claims: &Claims) -> Result<String, ServerError> {
+        let header = Header {
+            algorithm: self.private.algorithm,
+            key_id: Some(self.kid.clone()),
+            ..Default::default()
+        };
+        let token = Token::new(header, claims).sign_with_key(&self.private)?;
+        Ok(token.as_str().to_string())
+    }
+
+    fn decode(&self, token: &str) -> Option<Claims> {
+        let token: Token<Header, Claims, _> = token.verify_with_key(&self.public).ok()?;
+        Some(token.claims().clone())
+    }
+}
+
+/// a public key in jwk form, see rfc 7517.
+#[derive(Debug, Serialize)]
+pub struct Jwk {
+    kty: &'static str,
+    #[serde(rename = "use")]
+    usage: &'static str,
+    alg: AlgorithmType,
+    kid: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    e: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crv: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    x: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    y: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+fn encode(number: &BigNumRef) -> String {
+    URL_SAFE_NO_PAD.encode(number.to_vec())
+}
+
+impl KeyPair {
+    /// only ever built from the public half.
+    fn jwk(&self) -> Result<Jwk, ServerError> {
+        let key = &self.public.key;
+        let mut jwk = Jwk {
+            kty: "RSA",
+            usage: "sig",
+            alg: self.public.algorithm,
+            kid: self.kid.clone(),
+            n: None,
+            e: None,
+            crv: None,
+            x: None,
+            y: None,
+        };
+        if key.id() == Id::EC {
+            let ec = key.ec_key()?;
+            let mut x = BigNum::new()?;
+            let mut y = BigNum::new()?;
+            let mut context = BigNumContext::new()?;
+            ec.public_key()
+                .affine_coordinates(ec.group(), &mut x, &mut y, &mut context)?;
+            // coordinates are fixed width, keep their leading zeros
+            let x = x.to_vec_padded(P256_LEN as i32)?;
+            let y = y.to_vec_padded(P256_LEN as i32)?;
+            jwk.kty = "EC";
+            jwk.crv = Some("P-256");
+            jwk.x = Some(URL_SAFE_NO_PAD.encode(x));
+            jwk.y = Some(URL_SAFE_NO_PAD.encode(y));
+        } else {
+            let rsa = key.rsa()?;
+            jwk.n = Some(encode(rsa.n()));
+            jwk.e = Some(encode(rsa.e()));
+        }
+        Ok(jwk)
+    }
+}
+
+/// how session tokens are signed and verified.
+pub enum SigningScheme {
+    Hmac(Box<KeyRing>),
+    Rsa(KeyPair),
+    Ecdsa(KeyPair),
+}
+
+impl SigningScheme {
+    /// load the keys for `config`; HS256 keeps its secret at `key_path`.
+    pub fn load(config: &SigningConfig, key_path: &Path) -> Result<Self, ServerError> {
+        Ok(match config {
+            SigningConfig::Hs256 => Self::Hmac(Box::new(KeyRing::load(key_path)?)),
+            SigningConfig::Rs256 {
+                private_key,
+                public_key,
+            } => Self::Rsa(KeyPair::load(
+                AlgorithmType::Rs256,
+                private_key,
+                public_key,
+            )?),
+            SigningConfig::Es256 {
+                private_key,
+                public_key,
+            } => Self::Ecdsa(KeyPair::load(
+                AlgorithmType::Es256,
+                private_key,
+                public_key,
+            )?),
+        })
+    }
+
+    pub fn sign(&self, claims: &Claims) -> Result<String, ServerError> {
+        match self {
+            Self::Hmac(keys) => keys.sign(claims),
+            Self::Rsa(keys) | Self::Ecdsa(keys) => keys.sign(claims),
+        }
+    }
+
+    /// check the signature of `token`, returning its claims when valid. A
+    /// token signed with any other algorithm is rejected.
+    pub fn decode(&self, token: &str) -> Option<Claims> {
+        match self {
+            Self::Hmac(keys) => keys.decode(token),
+            Self::Rsa(keys) | Self::Ecdsa(keys) => keys.decode(token),
+        }
+    }
+
+    /// the public keys tokens can be verified with, none for HS256.
+    pub fn jwks(&self) -> Result<Jwks, ServerError> {
+        let keys = match self {
+            Self::Hmac(_) => vec![],
+            Self::Rsa(keys) | Self::Ecdsa(keys) => vec![keys.jwk()?],
+        };
+        Ok(Jwks { keys })
+    }
+
+    pub fn rotate(&mut self) -> Result<(), ServerError> {
+        match self {
+            Self::Hmac(keys) => keys.rotate(),
+            // the public key is handed out, so it has to be replaced by hand
+            Self::Rsa(_) | Self::Ecdsa(_) => Err(ServerError::Internal(String::from(
+                "asymmetric session keys are rotated by replacing the key files",
+            ))),
+        }
+    }
+
+    pub fn replace_all(&mut self) -> Result<(), ServerError> {
+        match self {
+            Self::Hmac(keys) => keys.replace_all(),
+            Self::Rsa(_) | Self::Ecdsa(_) => Err(ServerError::Internal(String::from(
+                "asymmetric session keys are replaced by replacing the key files",
+            ))),
+        }
+    }
+}
+
+fn random_secret() -> [u8; SECRET_LEN] {
+    let mut bytes = [0_u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn retired_path(path: &Path) -> PathBuf {
+    path.with_extension("retired")
+}
+
+fn staging_path(path: &Path) -> PathBuf {
+    path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()))
+}
+
+fn write_staging(path: &Path, bytes: &[u8]) -> Result<PathBuf, ServerError> {
+    let staging = staging_path(path);
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&staging)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    Ok(staging)
+}
+
+fn write_secret(path: &Path, bytes: &[u8]) -> Result<(), ServerError> {
+    let staging = write_staging(path, bytes)?;
+    fs::rename(&staging, path)?;
+    Ok(())
+}
+
+/// load the session secret from `path`, generating and persisting it on first run.
+fn session_secret(path: &Path) -> Result<[u8; SECRET_LEN], ServerError> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            return bytes
+                .try_into()
+                .map_err(|_| format!("invalid session key in {}", path.display()).into());
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    // a fresh deployment may not have a data directory yet
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // write the key aside first and link it into place, so a concurrent
+    // reader never sees a partially written file
+    let bytes = random_secret();
+    let staging = write_staging(path, &bytes)?;
+    let linked = fs::hard_link(&staging, path);
+    fs::remove_file(&staging)?;
+    match linked {
+        Ok(()) => Ok(bytes),
+        // another worker won the race, use its key instead
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => session_secret(path),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::SessionConfig;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("{}.key", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn session_secret_is_persisted() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path();
+        let first = session_secret(&path).unwrap();
+        let second = session_secret(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn session_secret_creates_missing_directory() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let path = dir.join("data").join("session.key");
+        let secret = session_secret(&path).unwrap();
+        let persisted = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(persisted, secret);
+    }
+
+    #[test]
+    fn session_secret_rejects_malformed_file() {
+        let path = temp_path();
+        fs::write(&path, b"short").unwrap();
+        let result = session_secret(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn sign_stamps_kid() {
+        let keys = KeyRing::ephemeral();
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+
+        let token: Token<Header, Claims, _> = Token::parse_unverified(&token).unwrap();
+        assert_eq!(token.header().key_id, Some(keys.primary.kid.clone()));
+    }
+
+    #[test]
+    fn retired_key_still_verifies() {
+        let mut keys = KeyRing::ephemeral();
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+
+        keys.rotate().unwrap();
+        assert!(keys.decode(&token).is_some());
+
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+        assert!(keys.decode(&token).is_some());
+    }
+
+    #[test]
+    fn decode_rejects_unknown_key() {
+        let keys = KeyRing::ephemeral();
+        let token = KeyRing::ephemeral()
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+
+        assert!(keys.decode(&token).is_none());
+    }
+
+    #[test]
+    fn decode_accepts_token_without_kid() {
+        let keys = KeyRing::ephemeral();
+        let token = Claims::new("user", 100, &SessionConfig::default())
+            .sign_with_key(&keys.primary.key)
+            .unwrap();
+
+        assert!(keys.decode(&token).is_some());
+    }
+
+    fn key_pair(key: PKey<Private>) -> (PathBuf, PathBuf) {
+        let private = temp_path();
+        let public = temp_path();
+        fs::write(&private, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        fs::write(&public, key.public_key_to_pem().unwrap()).unwrap();
+        (private, public)
+    }
+
+    fn rsa_key() -> PKey<Private> {
+        PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap()
+    }
+
+    fn load(config: &SigningConfig) -> SigningScheme {
+        let scheme = SigningScheme::load(config, &temp_path()).unwrap();
+        if let SigningConfig::Rs256 {
+            private_key,
+            public_key,
+        }
+        | SigningConfig::Es256 {
+            private_key,
+            public_key,
+        } = config
+        {
+            fs::remove_file(private_key).unwrap();
+            fs::remove_file(public_key).unwrap();
+        }
+        scheme
+    }
+
+    #[test]
+    fn rs256_round_trip() {
+        let (private_key, public_key) = key_pair(rsa_key());
+        let keys = load(&SigningConfig::Rs256 {
+            private_key,
+            public_key,
+        });
+        let claims = Claims::new("user", 100, &SessionConfig::default());
+        let token = keys.sign(&claims).unwrap();
+
+        let header: Token<Header, Claims, _> = Token::parse_unverified(&token).unwrap();
+        assert_eq!(header.header().algorithm, AlgorithmType::Rs256);
+        assert!(header.header().key_id.is_some());
+        assert_eq!(keys.decode(&token).unwrap().jti, claims.jti);
+
+        // neither another key pair nor the shared secret is accepted
+        let (private_key, public_key) = key_pair(rsa_key());
+        let other = load(&SigningConfig::Rs256 {
+            private_key,
+            public_key,
+        });
+        assert!(other.decode(&token).is_none());
+        let hmac = SigningScheme::Hmac(Box::new(KeyRing::ephemeral()));
+        assert!(keys.decode(&hmac.sign(&claims).unwrap()).is_none());
+        assert!(hmac.decode(&token).is_none());
+    }
+
+    #[test]
+    fn es256_round_trip() {
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            nid::Nid,
+        };
+
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let (private_key, public_key) = key_pair(key);
+        let keys = load(&SigningConfig::Es256 {
+            private_key,
+            public_key,
+        });
+        // about one signature in 128 has a short r or s, so sign enough
+        // tokens that the fixed width encoding is certain to be exercised
+        for _ in 0..1024 {
+            let claims = Claims::new("user", 100, &SessionConfig::default());
+            let token = keys.sign(&claims).unwrap();
+            let signature = token.rsplit('.').next().unwrap();
+            assert_eq!(URL_SAFE_NO_PAD.decode(signature).unwrap().len(), 64);
+            assert_eq!(keys.decode(&token).unwrap().jti, claims.jti);
+        }
+
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+        let header: Token<Header, Claims, _> = Token::parse_unverified(&token).unwrap();
+        assert_eq!(header.header().algorithm, AlgorithmType::Es256);
+
+        let jwks = serde_json::to_value(keys.jwks().unwrap()).unwrap();
+        let jwk = jwks["keys"][0].as_object().unwrap();
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["crv"], "P-256");
+        assert_eq!(jwk["x"].as_str().unwrap().len(), 43);
+        assert!(!jwk.contains_key("d"));
+    }
+
+    #[test]
+    fn jwks_exposes_public_key_only() {
+        let (private_key, public_key) = key_pair(rsa_key());
+        let keys = load(&SigningConfig::Rs256 {
+            private_key,
+            public_key,
+        });
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+        let header: Token<Header, Claims, _> = Token::parse_unverified(&token).unwrap();
+
+        let jwks = serde_json::to_value(keys.jwks().unwrap()).unwrap();
+        let jwk = jwks["keys"][0].as_object().unwrap();
+        assert_eq!(jwk["kty"], "RSA");
+        assert_eq!(jwk["alg"], "RS256");
+        assert_eq!(jwk["use"], "sig");
+        assert_eq!(jwk["kid"].as_str(), header.header().key_id.as_deref());
+        assert_eq!(jwk["e"], "AQAB");
+        for private in ["d", "p", "q", "dp", "dq", "qi", "k"] {
+            assert!(!jwk.contains_key(private));
+        }
+
+        let hmac = SigningScheme::Hmac(Box::new(KeyRing::ephemeral()));
+        assert!(hmac.jwks().unwrap().keys.is_empty());
+    }
+
+    #[test]
+    fn key_pair_rejects_mismatched_keys() {
+        let first = key_pair(rsa_key());
+        let second = key_pair(rsa_key());
+        let result = KeyPair::load(AlgorithmType::Rs256, &first.0, &second.1);
+        for path in [first.0, first.1, second.0, second.1] {
+            fs::remove_file(path).unwrap();
+        }
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn key_pair_rejects_keys_of_another_algorithm() {
+        use openssl::{
+            ec::{EcGroup, EcKey},
+            nid::Nid,
+        };
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let p384 = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        for (algorithm, key) in [
+            (AlgorithmType::Es256, rsa_key()),
+            (AlgorithmType::Es256, p384.clone()),
+            (AlgorithmType::Rs256, p384),
+        ] {
+            let (private_key, public_key) = key_pair(key);
+            let result = KeyPair::load(algorithm, &private_key, &public_key);
+            fs::remove_file(private_key).unwrap();
+            fs::remove_file(public_key).unwrap();
+            assert!(result.is_err());
+        }
+    }
+
+    #[test]
+    fn rotation_is_persisted() {
+        let path = temp_path();
+        let mut keys = KeyRing::load(&path).unwrap();
+        let token = keys
+            .sign(&Claims::new("user", 100, &SessionConfig::default()))
+            .unwrap();
+        keys.rotate().unwrap();
+
+        let reloaded = KeyRing::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(retired_path(&path)).unwrap();
+
+        assert_eq!(reloaded.primary.kid, keys.primary.kid);
+        assert!(reloaded.decode(&token).is_some());
+    }
+
+    #[test]
+    fn replace_all_invalidates_every_token() {
+        let path = temp_path();
+        let mut keys = KeyRing::load(&path).unwrap();
+        let sign = |keys: &KeyRing| {
+            keys.sign(&Claims::new("user", 100, &SessionConfig::default()))
+                .unwrap()
+        };
+        let retired = sign(&keys);
+        keys.rotate().unwrap();
+        let current = sign(&keys);
+        keys.replace_all().unwrap();
+
+        assert!(keys.decode(&retired).is_none());
+        assert!(keys.decode(&current).is_none());
+        assert!(keys.decode(&sign(&keys)).is_some());
+
+        let reloaded = KeyRing::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(retired_path(&path)).unwrap();
+        assert!(reloaded.retired.is_empty());
+        assert!(reloaded.decode(&current).is_none());
+    }
+}
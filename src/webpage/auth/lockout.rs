@@ -0,0 +1,127 @@
+use super::{SessionConfig, prune::Prunable};
+use std::{collections::HashMap, sync::Mutex};
+
+// the lockout stops doubling after this many extra failures
+const MAX_BACKOFF_SHIFT: u32 = 6;
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: u64,
+    locked_until: u64,
+}
+
+/// consecutive failed logins per account, to lock out credential stuffing.
+#[derive(Debug, Default)]
+pub struct LoginLockout {
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+impl LoginLockout {
+    /// seconds until `account` may try again, if it is locked out.
+    pub fn retry_after(&self, account: &str, now: u64) -> Option<u64> {
+        self.failures
+            .lock()
+            .expect("login lockout poisoned")
+            .get(account)
+            .filter(|failures| failures.locked_until > now)
+            .map(|failures| failures.locked_until - now)
+    }
+
+    pub fn failed(&self, account: &str, now: u64, config: &SessionConfig) {
+        self.prune(now, config);
+        let mut failures = self.failures.lock().expect("login lockout poisoned");
+
+        let entry = failures.entry(account.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: 0,
+        });
+        entry.count += 1;
+        entry.last = now;
+        if config.lockout_threshold > 0 && entry.count >= config.lockout_threshold {
+            // double the lockout with every failure past the threshold
+            let shift = (entry.count - config.lockout_threshold).min(MAX_BACKOFF_SHIFT);
+            entry.locked_until = now + (config.lockout_duration.as_secs() << shift);
+        }
+    }
+
+    pub fn succeeded(&self, account: &str) {
+        self.failures
+            .lock()
+            .expect("login lockout poisoned")
+            .remove(account);
+    }
+
+    #[cfg(test)]
+    pub(super) fn len(&self) -> usize {
+        self.failures.lock().unwrap().len()
+    }
+}
+
+impl Prunable for LoginLockout {
+    fn prune(&self, now: u64, config: &SessionConfig) {
+        let window = config.lockout_window.as_secs();
+        self.failures
+            .lock()
+            .expect("login lockout poisoned")
+            .retain(|_, failures| failures.last + window >= now || failures.locked_until > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn config() -> SessionConfig {
+        SessionConfig {
+            lockout_threshold: 3,
+            lockout_window: Duration::from_secs(600),
+            lockout_duration: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn repeated_failures_lock_out_with_backoff() {
+        let lockout = LoginLockout::default();
+        let config = config();
+
+        lockout.failed("user", 100, &config);
+        lockout.failed("user", 101, &config);
+        assert_eq!(lockout.retry_after("user", 102), None);
+
+        lockout.failed("user", 102, &config);
+        assert_eq!(lockout.retry_after("user", 102), Some(60));
+        assert_eq!(lockout.retry_after("other", 102), None);
+        assert_eq!(lockout.retry_after("user", 162), None);
+
+        lockout.failed("user", 170, &config);
+        assert_eq!(lockout.retry_after("user", 170), Some(120));
+    }
+
+    #[test]
+    fn success_resets_failures() {
+        let lockout = LoginLockout::default();
+        let config = config();
+
+        lockout.failed("user", 100, &config);
+        lockout.failed("user", 101, &config);
+        lockout.succeeded("user");
+        lockout.failed("user", 102, &config);
+        assert_eq!(lockout.retry_after("user", 102), None);
+    }
+
+    #[test]
+    fn failures_outside_window_are_forgotten() {
+        let lockout = LoginLockout::default();
+        let config = config();
+
+        lockout.failed("user", 100, &config);
+        lockout.failed("user", 101, &config);
+        lockout.failed("other", 1000, &config);
+        lockout.failed("user", 1000, &config);
+        assert_eq!(lockout.retry_after("user", 1000), None);
+    }
+}
@@ -1,83 +1,811 @@
-use crate::{config::CONFIG, error::ServerError};
+use crate::{
+    config::CONFIG,
+    database::login_history::LoginEvent,
+    error::ServerError,
+    webpage::{metrics::METRICS, request_id},
+};
 
-use super::{Claims, PRIVATE_KEY};
-use actix_web::{HttpResponse, Responder, post, web};
+use super::{
+    Claims, PASSWORD_PROVIDER, SessionConfig, SessionResponse, TokenType, client_ip,
+    clock::Clock,
+    cookie_token,
+    debounce::{LoginDebounce, Recent},
+    issue_token,
+    lockout::LoginLockout,
+    origin::SameOrigin,
+    respond,
+    revocation::RevocationStore,
+    secure_eq, session_cookie, session_cookie_for,
+    sink::{AuthEventKind, LoginSink, LoginSinks, record_auth_event, record_login_failure},
+    tracker::{SessionInfo, SessionTracker},
+    verify,
+};
+use actix_web::{
+    HttpRequest, HttpResponse, Responder,
+    http::header,
+    post,
+    rt::task::{self, JoinHandle},
+    web,
+};
 use chrono::DateTime;
-use jwt::SignWithKey;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    fs::OpenOptions,
-    io::Write,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    net::IpAddr,
+    sync::{Condvar, Mutex},
+    time::Duration,
 };
 
 #[derive(Debug, Deserialize)]
 struct Request {
     username: String,
     password: String,
-    ip: String,
+    #[serde(default)]
+    ip: Option<String>,
+    /// keep me logged in, for a session on a personal device
+    #[serde(default)]
+    remember_me: bool,
 }
 
 #[derive(Debug, Deserialize)]
 struct Logout {
-    username: String,
-    ip: String,
+    #[serde(default)]
+    ip: Option<String>,
+    #[serde(default)]
+    token: Option<String>,
+    #[serde(default)]
+    refresh_token: Option<String>,
+    /// also invalidate every other session of the token's owner
+    #[serde(default)]
+    all: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum LogoutScope {
+    Single,
+    All,
+}
+
+#[derive(Debug, Serialize)]
+struct LogoutResponse {
+    /// whether a valid token was presented and revoked
+    logged_out: bool,
+    scope: LogoutScope,
 }
 
-fn struct_claims() -> Claims {
-    let now = Duration::as_secs(
-        &SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Can't get time"),
+/// number of background records still running, so shutdown can wait for them.
+static PENDING: Mutex<usize> = Mutex::new(0);
+static IDLE: Condvar = Condvar::new();
+
+struct PendingGuard;
+
+impl PendingGuard {
+    fn new() -> Self {
+        *PENDING.lock().expect("pending records poisoned") += 1;
+        Self
+    }
+}
+
+impl Drop for PendingGuard {
+    fn drop(&mut self) {
+        let mut pending = PENDING.lock().expect("pending records poisoned");
+        *pending -= 1;
+        if *pending == 0 {
+            IDLE.notify_all();
+        }
+    }
+}
+
+/// run `job` off the actix worker, logging rather than propagating its failure.
+fn record_in_background<F>(job: F) -> JoinHandle<()>
+where
+    F: FnOnce() -> Result<(), ServerError> + Send + 'static,
+{
+    let guard = PendingGuard::new();
+    // the blocking thread is outside the request, carry its correlation id over
+    let id = request_id::current();
+    task::spawn_blocking(move || {
+        let _guard = guard;
+        if let Err(err) = request_id::scope(id, job) {
+            log::error!("fail to record auth event: {:?}", err);
+        }
+    })
+}
+
+/// block until every background record has finished, or `timeout` passes.
+/// Returns whether everything was flushed.
+pub fn drain_pending_records(timeout: Duration) -> bool {
+    let pending = PENDING.lock().expect("pending records poisoned");
+    let (pending, _) = IDLE
+        .wait_timeout_while(pending, timeout, |pending| *pending > 0)
+        .expect("pending records poisoned");
+    *pending == 0
+}
+
+const MAX_USER_AGENT_LEN: usize = 256;
+
+/// the client's `User-Agent`, without control characters and cut to a sane length.
+pub(super) fn user_agent(request: &HttpRequest) -> String {
+    let user_agent: String = request
+        .headers()
+        .get(header::USER_AGENT)
+        .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_USER_AGENT_LEN)
+        .collect();
+    match user_agent.trim() {
+        "" => String::from("unknown"),
+        user_agent => user_agent.to_string(),
+    }
+}
+
+fn display_ip(ip: Option<IpAddr>) -> String {
+    ip.map_or_else(|| String::from("unknown"), |ip| ip.to_string())
+}
+
+/// a session granted by [`attempt`], or the response refusing one.
+enum Attempt {
+    Granted {
+        claims: Box<Claims>,
+        token: String,
+        refresh_token: String,
+    },
+    Denied(HttpResponse),
+}
+
+/// the session just granted to `sub`, for a login repeated within the
+/// debounce window with the same `remember` choice. It is only handed out
+/// again while it is still on its first token and the client could present it.
+fn debounced(
+    http: &HttpRequest,
+    sub: &str,
+    remember: bool,
+    now: u64,
+    config: &SessionConfig,
+    tracker: &SessionTracker,
+) -> Option<Recent> {
+    let debounce = http.app_data::<web::Data<LoginDebounce>>()?;
+    let recent = debounce.recent(PASSWORD_PROVIDER, sub, remember, now, config)?;
+    let live = tracker
+        .list(sub, now)
+        .iter()
+        .any(|(sid, info)| *sid == recent.claims.sid && info.jti == recent.claims.jti);
+    (live && recent.claims.bound_to(http, config)).then_some(recent)
+}
+
+/// count a refused login, and log it unless configured not to. The login is
+/// refused all the same if it cannot be logged.
+fn refused(
+    http: &HttpRequest,
+    request: &Request,
+    reason: &'static str,
+    now: u64,
+    config: &SessionConfig,
+) {
+    METRICS.login_failed(reason);
+    if config.log_failed_logins {
+        let ip = display_ip(client_ip(http, request.ip.as_deref(), config));
+        if let Err(err) =
+            record_login_failure(&request.username, &ip, reason, now, &config.deployment_id)
+        {
+            log::error!("fail to record refused login: {:?}", err);
+        }
+    }
+}
+
+/// check the credentials of `request` and start a session, shared by the
+/// login endpoints which only differ in how they hand the token out.
+fn attempt(
+    http: &HttpRequest,
+    request: &Request,
+    config: &SessionConfig,
+    clock: &dyn Clock,
+    tracker: &SessionTracker,
+    lockout: &LoginLockout,
+    sinks: web::Data<LoginSinks>,
+) -> Result<Attempt, ServerError> {
+    let now = clock.now_secs();
+    if let Some(retry_after) = lockout.retry_after(&request.username, now) {
+        refused(http, request, "locked_out", now, config);
+        return Ok(Attempt::Denied(
+            HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, retry_after.to_string()))
+                .finish(),
+        ));
+    }
+
+    // evaluate both, so a wrong username costs as much as a wrong password
+    let username = secure_eq(&CONFIG.username, &request.username);
+    let password = secure_eq(&CONFIG.password, &request.password);
+    if !(username & password) {
+        lockout.failed(&request.username, now, config);
+        refused(http, request, "invalid_credential", now, config);
+        return Ok(Attempt::Denied(HttpResponse::Forbidden().finish()));
+    }
+
+    lockout.succeeded(&request.username);
+    let ip = display_ip(client_ip(http, request.ip.as_deref(), config));
+    let user_agent = user_agent(http);
+    let repeated = debounced(
+        http,
+        &request.username,
+        request.remember_me,
+        now,
+        config,
+        tracker,
     );
-    return Claims {
-        iat: now,
-        exp: now + 3600,
+    let (claims, token, refresh_token) = match repeated {
+        Some(recent) => {
+            log::info!(
+                "repeated login of {}, reusing session {}",
+                recent.claims.sub,
+                recent.claims.sid
+            );
+            (recent.claims, recent.token, recent.refresh_token)
+        }
+        None => {
+            let claims = Claims::new(&request.username, now, config)
+                .remembered(request.remember_me, config)
+                .bind(http, config);
+            let token = issue_token(&claims)?;
+            let refresh = claims.to_refresh_token(config);
+            let refresh_token = issue_token(&refresh)?;
+            tracker.start(
+                &claims.sid,
+                SessionInfo {
+                    sub: claims.sub.clone(),
+                    issued_at: claims.iat,
+                    last_active: claims.iat,
+                    ip: ip.clone(),
+                    user_agent: user_agent.clone(),
+                    jti: claims.jti.clone(),
+                    expires_at: claims.exp.max(refresh.exp),
+                },
+                claims.iat,
+            );
+            if let Some(debounce) = http.app_data::<web::Data<LoginDebounce>>() {
+                debounce.remember(
+                    Recent {
+                        claims: claims.clone(),
+                        token: token.clone(),
+                        refresh_token: refresh_token.clone(),
+                    },
+                    config,
+                );
+            }
+            (claims, token, refresh_token)
+        }
+    };
+
+    // a repeated login is still a login, it is counted and recorded as one
+    let event = LoginEvent {
+        id: 0,
+        sub: claims.sub.clone(),
+        provider: claims.provider.clone(),
+        ip,
+        user_agent,
+        created_at: DateTime::from_timestamp(now as i64, 0).expect("Can't get time"),
+    };
+    let sinks = sinks.into_inner();
+    record_in_background(move || sinks.record(&event));
+
+    METRICS.login_succeeded();
+    Ok(Attempt::Granted {
+        claims: Box::new(claims),
+        token,
+        refresh_token,
+    })
+}
+
+#[post("/login")]
+pub async fn login_handler(
+    http: HttpRequest,
+    _: SameOrigin,
+    request: web::Json<Request>,
+    config: web::Data<SessionConfig>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+    (lockout, sinks): (web::Data<LoginLockout>, web::Data<LoginSinks>),
+) -> Result<impl Responder, ServerError> {
+    let (claims, token, refresh_token) = match attempt(
+        &http, &request, &config, &**clock, &tracker, &lockout, sinks,
+    )? {
+        Attempt::Granted {
+            claims,
+            token,
+            refresh_token,
+        } => (claims, token, refresh_token),
+        Attempt::Denied(response) => return Ok(response),
     };
+
+    let mut response = HttpResponse::Ok();
+    if config.set_cookie {
+        response.cookie(session_cookie_for(&token, &claims, &config));
+    }
+    let body = SessionResponse {
+        refresh_token: Some(refresh_token),
+        ..SessionResponse::from_claims(token, &claims)
+    };
+    respond(response, &body, &http)
+}
+
+/// what a cookie login reveals of the session, without any token.
+#[derive(Debug, Serialize)]
+struct Profile {
+    sub: String,
+    provider: String,
+    roles: Vec<String>,
+    issued_at: u64,
+    expires_at: u64,
+}
+
+/// the session lives in the cookie only, out of reach of scripts.
+fn cookie_response(token: &str, claims: &Claims, config: &SessionConfig) -> HttpResponse {
+    HttpResponse::Ok()
+        .cookie(session_cookie_for(token, claims, config))
+        .json(Profile {
+            sub: claims.sub.clone(),
+            provider: claims.provider.clone(),
+            roles: claims.roles.clone(),
+            issued_at: claims.iat,
+            expires_at: claims.exp,
+        })
+}
+
+/// like [`login_handler`], but the token is only ever set as a cookie.
+#[post("/login/cookie")]
+pub async fn cookie_login_handler(
+    http: HttpRequest,
+    _: SameOrigin,
+    request: web::Json<Request>,
+    config: web::Data<SessionConfig>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+    (lockout, sinks): (web::Data<LoginLockout>, web::Data<LoginSinks>),
+) -> Result<impl Responder, ServerError> {
+    match attempt(
+        &http, &request, &config, &**clock, &tracker, &lockout, sinks,
+    )? {
+        Attempt::Granted { claims, token, .. } => Ok(cookie_response(&token, &claims, &config)),
+        Attempt::Denied(response) => Ok(response),
+    }
 }
 
-#[post("/api/auth/login")]
-pub async fn login_handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
-    if CONFIG.username == request.username && CONFIG.password == request.password {
-        let claims = struct_claims();
-        let token = claims.clone().sign_with_key(&*PRIVATE_KEY)?;
-        let iat_date_string = DateTime::from_timestamp(claims.clone().iat as i64, 0)
-            .expect("Can't get time")
-            .to_string();
-        let exp_date_string = DateTime::from_timestamp(claims.clone().exp as i64, 0)
-            .expect("Can't get time")
-            .to_string();
-        let log = "[Login] User ".to_string()
-            + &request.username
-            + " logged in on "
-            + &iat_date_string
-            + " at "
-            + &request.ip
-            + ", whose session expires on "
-            + &exp_date_string
-            + ".";
-        let log_file = OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open("data/login_history.log");
-        writeln!(log_file?, "{log}")?;
-
-        Ok(HttpResponse::Ok().body(token))
-    } else {
-        Ok(HttpResponse::Forbidden().finish())
-    }
-}
-
-#[post("/api/auth/logout")]
-pub async fn logout_logging(request: web::Json<Logout>) -> String {
-    let claims = struct_claims();
-    let log = "[Login] User ".to_string()
-        + &request.username
-        + " logged out on "
-        + &claims.clone().iat.to_string()
-        + " at "
-        + &request.ip
-        + ".";
-    log::info!("{}", log);
-    return log;
+#[post("/logout")]
+pub async fn logout_logging(
+    http: HttpRequest,
+    _: SameOrigin,
+    request: web::Json<Logout>,
+    config: web::Data<SessionConfig>,
+    revocations: web::Data<RevocationStore>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+) -> Result<impl Responder, ServerError> {
+    let now = clock.now_secs();
+    let cookie = cookie_token(&http);
+    let presented = [
+        (
+            request.token.as_deref().or(cookie.as_deref()),
+            TokenType::Access,
+        ),
+        (request.refresh_token.as_deref(), TokenType::Refresh),
+    ];
+    // only a verified token says whose session ended
+    let mut subject = None;
+    for (token, token_type) in presented {
+        if let Some(claims) =
+            token.and_then(|token| verify(token, token_type, now, &config, &revocations))
+        {
+            revocations.revoke(&claims.jti, claims.exp, now, &config);
+            tracker.end(&claims.sid);
+            if request.all {
+                revocations.revoke_all(&claims.sub, now);
+                tracker.end_all(&claims.sub);
+            }
+            subject = Some(claims.sub);
+        }
+    }
+    METRICS.logged_out();
+
+    // the session is over either way, a failing audit log must not hide that
+    if let Some(sub) = &subject {
+        let ip = display_ip(client_ip(&http, request.ip.as_deref(), &config));
+        if let Err(err) =
+            record_auth_event(AuthEventKind::Logout, sub, &ip, now, &config.deployment_id)
+        {
+            log::error!("fail to record logout of {}: {:?}", sub, err);
+        }
+    }
+
+    let mut response = HttpResponse::Ok();
+    if cookie.is_some() {
+        let mut removal = session_cookie("", &config);
+        removal.make_removal();
+        response.cookie(removal);
+    }
+    Ok(response.json(LogoutResponse {
+        logged_out: subject.is_some(),
+        scope: if request.all {
+            LogoutScope::All
+        } else {
+            LogoutScope::Single
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{TEST_NOW, configure, test_clock};
+    use actix_web::cookie;
+    use actix_web::{
+        App,
+        http::StatusCode,
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn user_agent_is_sanitized() {
+        let request = TestRequest::default()
+            .insert_header((header::USER_AGENT, "Mozilla/5.0\t(X11)"))
+            .to_http_request();
+        assert_eq!(user_agent(&request), "Mozilla/5.0(X11)");
+
+        let request = TestRequest::default()
+            .insert_header((header::USER_AGENT, "a".repeat(1000)))
+            .to_http_request();
+        assert_eq!(user_agent(&request).len(), MAX_USER_AGENT_LEN);
+
+        let request = TestRequest::default().to_http_request();
+        assert_eq!(user_agent(&request), "unknown");
+    }
+
+    #[test]
+    fn repeated_login_shares_live_session() {
+        let config = SessionConfig {
+            login_debounce: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let debounce = web::Data::new(LoginDebounce::default());
+        let http = TestRequest::default()
+            .app_data(debounce.clone())
+            .to_http_request();
+        let tracker = SessionTracker::default();
+        let claims = Claims::new("user", TEST_NOW, &config);
+        tracker.start(
+            &claims.sid,
+            SessionInfo {
+                sub: claims.sub.clone(),
+                issued_at: claims.iat,
+                last_active: claims.iat,
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                jti: claims.jti.clone(),
+                expires_at: claims.exp,
+            },
+            claims.iat,
+        );
+        debounce.remember(
+            Recent {
+                claims: claims.clone(),
+                token: String::from("token"),
+                refresh_token: String::from("refresh"),
+            },
+            &config,
+        );
+
+        let shared = debounced(&http, "user", false, TEST_NOW + 1, &config, &tracker).unwrap();
+        assert_eq!(shared.claims.sid, claims.sid);
+        assert_eq!(shared.token, "token");
+        assert_eq!(tracker.list("user", TEST_NOW + 1).len(), 1);
+        // asking to be remembered is a different login
+        assert!(debounced(&http, "user", true, TEST_NOW + 1, &config, &tracker).is_none());
+
+        // once ticked, the first token is spent and a new session is due
+        tracker.touch(&claims.sid, "next", TEST_NOW + 2, claims.exp);
+        assert!(debounced(&http, "user", false, TEST_NOW + 2, &config, &tracker).is_none());
+    }
+
+    #[actix_web::test]
+    async fn background_records_do_not_block_each_other() {
+        use std::{
+            sync::{Arc, Mutex},
+            thread,
+            time::Instant,
+        };
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let start = Instant::now();
+        let jobs: Vec<_> = (0..16)
+            .map(|i| {
+                let recorded = recorded.clone();
+                record_in_background(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    recorded.lock().unwrap().push(i);
+                    Ok(())
+                })
+            })
+            .collect();
+        // spawning does not wait for the writes
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        for job in jobs {
+            job.await.unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(16 * 100));
+        assert_eq!(recorded.lock().unwrap().len(), 16);
+    }
+
+    #[actix_web::test]
+    async fn drain_waits_for_queued_records() {
+        use std::{
+            sync::{Arc, Mutex},
+            thread,
+        };
+
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        for i in 0..4 {
+            let recorded = recorded.clone();
+            record_in_background(move || {
+                thread::sleep(Duration::from_millis(100));
+                recorded.lock().unwrap().push(i);
+                Ok(())
+            });
+        }
+
+        let drained = task::spawn_blocking(|| drain_pending_records(Duration::from_secs(10)))
+            .await
+            .unwrap();
+        assert!(drained);
+        assert_eq!(recorded.lock().unwrap().len(), 4);
+    }
+
+    #[actix_web::test]
+    async fn drain_gives_up_after_timeout() {
+        let job = record_in_background(|| {
+            std::thread::sleep(Duration::from_millis(500));
+            Ok(())
+        });
+
+        let drained = task::spawn_blocking(|| drain_pending_records(Duration::from_millis(50)))
+            .await
+            .unwrap();
+        assert!(!drained);
+        job.await.unwrap();
+    }
+
+    #[actix_web::test]
+    async fn failed_background_record_is_swallowed() {
+        let job = record_in_background(|| Err(ServerError::Internal(String::from("disk full"))));
+        assert!(job.await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn background_record_keeps_request_id() {
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(None));
+        let job = {
+            let seen = seen.clone();
+            request_id::scope(Some(String::from("abc-123")), || {
+                record_in_background(move || {
+                    *seen.lock().unwrap() = request_id::current();
+                    Ok(())
+                })
+            })
+        };
+        job.await.unwrap();
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("abc-123"));
+    }
+
+    #[actix_web::test]
+    async fn cookie_login_keeps_token_out_of_body() {
+        use actix_web::body::to_bytes;
+
+        let config = SessionConfig::default();
+        let claims = Claims::new("user", TEST_NOW, &config);
+        let token = issue_token(&claims).unwrap();
+        let response = cookie_response(&token, &claims, &config);
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = response
+            .cookies()
+            .find(|cookie| cookie.name() == "session")
+            .unwrap();
+        assert_eq!(cookie.value(), token);
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.same_site(), Some(cookie::SameSite::Strict));
+
+        let body = to_bytes(response.into_body()).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains(&token));
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["sub"], "user");
+        assert_eq!(body["provider"], PASSWORD_PROVIDER);
+        assert!(body.get("token").is_none());
+        assert!(body.get("refresh_token").is_none());
+    }
+
+    #[actix_web::test]
+    async fn cookie_session_survives_ticks() {
+        let config = SessionConfig::default();
+        let tracker = web::Data::new(SessionTracker::default());
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        // what a cookie login at `attempt` leaves behind
+        let claims = Claims::new("user", TEST_NOW - 10, &config);
+        tracker.start(
+            &claims.sid,
+            SessionInfo {
+                sub: claims.sub.clone(),
+                issued_at: claims.iat,
+                last_active: claims.iat,
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                jti: claims.jti.clone(),
+                expires_at: claims.exp,
+            },
+            claims.iat,
+        );
+        let login = cookie_response(&issue_token(&claims).unwrap(), &claims, &config);
+        let mut cookie = login
+            .cookies()
+            .find(|cookie| cookie.name() == "session")
+            .unwrap()
+            .into_owned();
+
+        for _ in 0..2 {
+            let request = TestRequest::post()
+                .uri("/api/auth/tick")
+                .cookie(cookie.clone())
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let next = response
+                .response()
+                .cookies()
+                .find(|cookie| cookie.name() == "session")
+                .unwrap()
+                .into_owned();
+            assert_ne!(next.value(), cookie.value());
+            let body: serde_json::Value =
+                serde_json::from_slice(&read_body(response).await).unwrap();
+            assert_eq!(body["sub"], "user");
+            assert!(body.get("token").is_none());
+            cookie = next;
+        }
+
+        let request = TestRequest::get()
+            .uri("/api/auth/session")
+            .cookie(cookie)
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert!(body.get("token").is_none());
+    }
+
+    #[actix_web::test]
+    async fn malformed_body_is_structured_error() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+
+        for body in ["", "{", "{\"username\": \"user\"}", "[]"] {
+            let request = TestRequest::post()
+                .uri("/api/auth/login")
+                .insert_header(("content-type", "application/json"))
+                .set_payload(body)
+                .to_request();
+            let response = call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{body}");
+            let body: serde_json::Value =
+                serde_json::from_slice(&read_body(response).await).unwrap();
+            assert_eq!(body["error"], "invalid_request_body");
+            assert!(body["message"].is_string());
+        }
+    }
+
+    #[actix_web::test]
+    async fn logout_confirms_scope_and_clears_cookie() {
+        let config = SessionConfig::default();
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let token = issue_token(&Claims::new("user", TEST_NOW - 1, &config)).unwrap();
+
+        let request = TestRequest::post()
+            .uri("/api/auth/logout")
+            .cookie(session_cookie(&token, &config))
+            .set_json(json!({ "username": "user", "all": true }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let removal = response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "session")
+            .unwrap();
+        assert_eq!(removal.value(), "");
+        assert_eq!(removal.max_age(), Some(cookie::time::Duration::ZERO));
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert_eq!(body, json!({ "logged_out": true, "scope": "all" }));
+
+        // nothing left to log out, and no cookie to clear
+        let request = TestRequest::post()
+            .uri("/api/auth/logout")
+            .set_json(json!({ "username": "user", "token": token }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.response().cookies().count(), 0);
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert_eq!(body, json!({ "logged_out": false, "scope": "single" }));
+    }
+
+    #[actix_web::test]
+    async fn logged_out_token_is_rejected_by_tick() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let token = issue_token(&Claims::new(
+            "user",
+            TEST_NOW - 1,
+            &SessionConfig::default(),
+        ))
+        .unwrap();
+
+        // the presented token is spent by the tick, so log out with the new one
+        let request = TestRequest::post()
+            .uri("/api/auth/tick")
+            .set_json(json!({ "token": token }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        let token = body["token"].as_str().unwrap().to_string();
+
+        let request = TestRequest::post()
+            .uri("/api/auth/logout")
+            .set_json(json!({ "username": "user", "ip": "127.0.0.1", "token": token }))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        assert_eq!(body["logged_out"], true);
+
+        let request = TestRequest::post()
+            .uri("/api/auth/tick")
+            .set_json(json!({ "token": token }))
+            .to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::FORBIDDEN
+        );
+    }
 }
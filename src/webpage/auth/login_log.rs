@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+pub const LOGIN_LOG_PATH: &str = "data/login_history.log";
+
+// serializes rollover against appends from other actix workers
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginLogConfig {
+    /// size in bytes after which the log is rolled over
+    pub max_size: u64,
+    /// number of rolled over files to keep, as `<log>.1` to `<log>.N`
+    pub archives: usize,
+}
+
+impl Default for LoginLogConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10 * 1024 * 1024,
+            archives: 5,
+        }
+    }
+}
+
+fn archive_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+fn roll_over(path: &Path, config: &LoginLogConfig) -> io::Result<()> {
+    if config.archives == 0 {
+        return ignore_missing(fs::remove_file(path));
+    }
+    for index in (1..config.archives).rev() {
+        ignore_missing(fs::rename(
+            archive_path(path, index),
+            archive_path(path, index + 1),
+        ))?;
+    }
+    fs::rename(path, archive_path(path, 1))
+}
+
+/// append `line` to the log at `path`, rolling it over first if it would
+/// grow past `config.max_size`. A missing parent directory is created.
+pub fn append(path: &Path, line: &str, config: &LoginLogConfig) -> io::Result<()> {
+    let _guard = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            0
+        }
+        Err(err) => return Err(err),
+    };
+    if size > 0 && size + line.len() as u64 + 1 > config.max_size {
+        roll_over(path, config)?;
+    }
+
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    writeln!(file, "{line}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_rolls_over_past_max_size() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("login_history.log");
+        let config = LoginLogConfig {
+            max_size: 64,
+            archives: 2,
+        };
+
+        let line = "x".repeat(40);
+        append(&path, &line, &config).unwrap();
+        assert!(!archive_path(&path, 1).exists());
+
+        append(&path, &line, &config).unwrap();
+        assert!(archive_path(&path, 1).exists());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 41);
+
+        append(&path, &line, &config).unwrap();
+        append(&path, &line, &config).unwrap();
+        assert!(archive_path(&path, 2).exists());
+        assert!(!archive_path(&path, 3).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append_creates_missing_directory() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let path = dir.join("data").join("login_history.log");
+
+        append(&path, "line", &LoginLogConfig::default()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "line\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,54 +1,1393 @@
+pub mod batch;
+pub mod clock;
+pub mod debounce;
 pub mod google;
+#[cfg(test)]
+mod harness;
+pub mod inflight;
+pub mod jwks;
+mod keys;
+pub mod lockout;
 pub mod login;
+#[cfg(feature = "login-log-file")]
+pub mod login_log;
+pub mod my_logins;
+pub mod origin;
+pub mod prune;
+pub mod refresh;
+pub mod revocation;
+pub mod session;
+pub mod sessions;
+pub mod sink;
 pub mod tick;
+pub mod tracker;
 
-use hmac::{Hmac, Mac};
-use jwt::{Header, Token, VerifyWithKey};
-use rand::RngCore;
+use crate::{config::CONFIG, error::ServerError};
+use actix_web::{
+    FromRequest, HttpMessage, HttpRequest, HttpResponse, HttpResponseBuilder,
+    cookie::{self, Cookie, SameSite},
+    dev::Payload,
+    error::JsonPayloadError,
+    http::header,
+    middleware, web,
+};
+use clock::Clock;
+use keys::{KeyRing, SigningScheme};
+use rand::distributions::{Alphanumeric, DistString};
+use revocation::RevocationStore;
 use serde::{Deserialize, Serialize};
-use sha2::Sha256;
-use std::{clone::Clone, sync::LazyLock};
-
-static PRIVATE_KEY: LazyLock<Hmac<Sha256>> = LazyLock::new(|| {
-    let mut rng = rand::thread_rng();
-    let mut bytes = [0_u8; 32];
-    rng.fill_bytes(&mut bytes);
-    Hmac::new_from_slice(&bytes).expect("fail to generate HMAC key.")
+use sha2::{Digest, Sha256};
+use std::{
+    clone::Clone,
+    collections::HashMap,
+    future::{Ready, ready},
+    marker::PhantomData,
+    net::IpAddr,
+    path::Path,
+    sync::{LazyLock, RwLock},
+    time::Duration,
+};
+use subtle::ConstantTimeEq;
+
+const ISSUER: &str = "mercuryland";
+const SESSION_COOKIE: &str = "session";
+const SESSION_KEY_PATH: &str = "data/session.key";
+/// the only way to log in so far
+pub const PASSWORD_PROVIDER: &str = "password";
+
+pub use keys::SigningConfig;
+
+static KEYS: LazyLock<RwLock<SigningScheme>> = LazyLock::new(|| {
+    // tests should not touch the deployment key
+    let keys = if cfg!(test) {
+        SigningScheme::Hmac(Box::new(KeyRing::ephemeral()))
+    } else {
+        SigningScheme::load(&CONFIG.session.signing, Path::new(SESSION_KEY_PATH))
+            .expect("fail to load session key.")
+    };
+    RwLock::new(keys)
 });
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// lifetime of an access token, in seconds
+    #[serde(with = "seconds")]
+    pub ttl: Duration,
+    /// lifetime of a refresh token, in seconds
+    #[serde(with = "seconds")]
+    pub refresh_ttl: Duration,
+    /// how long after the initial login a session may still be refreshed, in seconds
+    #[serde(with = "seconds")]
+    pub max_lifetime: Duration,
+    /// tolerated clock skew when checking `iat` and `exp`, in seconds
+    #[serde(with = "seconds")]
+    pub leeway: Duration,
+    /// proxies whose `X-Forwarded-For` header is trusted for the client ip
+    pub trusted_proxies: Vec<IpAddr>,
+    /// roles granted to each username at login
+    pub roles: HashMap<String, Vec<String>>,
+    /// `aud` stamped on and required of session tokens, set per environment
+    pub audience: String,
+    /// also hand the session token out as an `HttpOnly` cookie
+    pub set_cookie: bool,
+    /// logins of one user from distant networks this close together are
+    /// flagged as suspicious, in seconds
+    #[serde(with = "seconds")]
+    pub suspicious_login_window: Duration,
+    /// how long a session may go without a tick before it can no longer be
+    /// refreshed, in seconds, unlimited if absent
+    #[serde(with = "seconds::option")]
+    pub idle_timeout: Option<Duration>,
+    /// consecutive failed logins of one account before it is locked out, 0 to disable
+    pub lockout_threshold: u32,
+    /// how long failed logins are remembered, in seconds
+    #[serde(with = "seconds")]
+    pub lockout_window: Duration,
+    /// first lockout, doubled with every further failure, in seconds
+    #[serde(with = "seconds")]
+    pub lockout_duration: Duration,
+    /// algorithm and keys session tokens are signed with
+    pub signing: SigningConfig,
+    /// origins allowed to post to the auth endpoints, empty to not check
+    pub allowed_origins: Vec<String>,
+    /// names this deployment in auth events, to tell aggregated logs apart
+    pub deployment_id: String,
+    /// also stamp `deployment_id` into session tokens
+    pub deployment_claim: bool,
+    /// bind tokens to the `User-Agent` they were issued to; a browser update
+    /// then ends the session
+    pub bind_user_agent: bool,
+    /// auth requests processed at once before further ones are turned away
+    /// with a 503, 0 for no limit
+    pub max_in_flight: usize,
+    /// a second login of the same user this soon after the first gets the
+    /// first one's session, in seconds, off if absent
+    #[serde(with = "seconds::option")]
+    pub login_debounce: Option<Duration>,
+    /// also log refused logins, with why they were refused
+    pub log_failed_logins: bool,
+    /// longest `exp - iat` a token may claim, in seconds; if absent, the
+    /// lifetime this deployment issues tokens of that type with, plus `leeway`
+    #[serde(with = "seconds::option")]
+    pub max_token_lifetime: Option<Duration>,
+    /// `Domain` of the session cookie, for deployments spanning subdomains;
+    /// host-only if absent
+    pub cookie_domain: Option<String>,
+    /// `Path` of the session cookie
+    pub cookie_path: String,
+    /// how often expired entries are swept from the in-memory stores, in seconds
+    #[serde(with = "seconds")]
+    pub prune_interval: Duration,
+    /// lifetime of an access token when the user asks to be remembered at
+    /// login, in seconds, instead of `ttl`
+    #[serde(with = "seconds")]
+    pub remember_me_ttl: Duration,
+}
+
+impl SessionConfig {
+    fn max_token_lifetime(&self, token_type: TokenType) -> u64 {
+        let issued = match token_type {
+            TokenType::Access => self.ttl.max(self.remember_me_ttl),
+            TokenType::Refresh => self.refresh_ttl,
+        };
+        self.max_token_lifetime
+            .unwrap_or(issued + self.leeway)
+            .as_secs()
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(3600),
+            refresh_ttl: Duration::from_secs(12 * 3600),
+            max_lifetime: Duration::from_secs(12 * 3600),
+            leeway: Duration::from_secs(30),
+            trusted_proxies: vec![],
+            roles: HashMap::new(),
+            audience: String::from(ISSUER),
+            set_cookie: false,
+            suspicious_login_window: Duration::from_secs(3600),
+            idle_timeout: None,
+            lockout_threshold: 5,
+            lockout_window: Duration::from_secs(15 * 60),
+            lockout_duration: Duration::from_secs(60),
+            signing: SigningConfig::default(),
+            allowed_origins: vec![],
+            deployment_id: String::from("default"),
+            deployment_claim: false,
+            bind_user_agent: false,
+            max_in_flight: 64,
+            login_debounce: None,
+            log_failed_logins: true,
+            max_token_lifetime: None,
+            cookie_domain: None,
+            cookie_path: String::from("/api"),
+            prune_interval: Duration::from_secs(300),
+            remember_me_ttl: Duration::from_secs(7 * 24 * 3600),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenType {
+    #[default]
+    Access,
+    Refresh,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct Claims {
-    iat: u64,
-    exp: u64,
+pub struct Claims {
+    #[serde(default)]
+    iss: String,
+    #[serde(default)]
+    aud: String,
+    // the authenticated username, empty for tokens issued before it existed
+    #[serde(default)]
+    pub sub: String,
+    pub iat: u64,
+    pub exp: u64,
+    // tokens issued before `auth_time` existed fall back to `iat`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth_time: Option<u64>,
+    // the last login or tick, falls back to `iat` like `auth_time`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_active: Option<u64>,
+    #[serde(default)]
+    jti: String,
+    // shared by every token of one login, empty for tokens issued before it existed
+    #[serde(default)]
+    sid: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    // tokens issued before refresh tokens existed are access tokens
+    #[serde(default)]
+    pub token_type: TokenType,
+    // only stamped when `deployment_claim` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deployment: Option<String>,
+    // the login provider, tokens issued before it existed came from the password login
+    #[serde(default = "password_provider")]
+    pub provider: String,
+    // hash of the user agent the session was issued to, see `bind_user_agent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uah: Option<String>,
+    // the user asked to be remembered, tokens live for `remember_me_ttl`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    remember: bool,
+}
+
+fn password_provider() -> String {
+    String::from(PASSWORD_PROVIDER)
+}
+
+impl Claims {
+    fn new(sub: &str, now: u64, config: &SessionConfig) -> Self {
+        Self {
+            iss: String::from(ISSUER),
+            aud: config.audience.clone(),
+            sub: sub.to_string(),
+            iat: now,
+            exp: now + config.ttl.as_secs(),
+            auth_time: Some(now),
+            last_active: Some(now),
+            jti: Alphanumeric.sample_string(&mut rand::thread_rng(), 16),
+            sid: Alphanumeric.sample_string(&mut rand::thread_rng(), 16),
+            roles: config.roles.get(sub).cloned().unwrap_or_default(),
+            token_type: TokenType::Access,
+            deployment: config
+                .deployment_claim
+                .then(|| config.deployment_id.clone()),
+            provider: String::from(PASSWORD_PROVIDER),
+            uah: None,
+            remember: false,
+        }
+    }
+
+    /// a refresh token for the same login, valid for `config.refresh_ttl`.
+    fn to_refresh_token(&self, config: &SessionConfig) -> Self {
+        Self {
+            token_type: TokenType::Refresh,
+            exp: self.iat + config.refresh_ttl.as_secs(),
+            jti: Alphanumeric.sample_string(&mut rand::thread_rng(), 16),
+            ..self.clone()
+        }
+    }
+
+    /// re-issue the claims for a new window, keeping the original login time.
+    fn refresh(&self, now: u64, config: &SessionConfig) -> Self {
+        Self {
+            auth_time: Some(self.auth_time()),
+            sid: self.sid.clone(),
+            provider: self.provider.clone(),
+            uah: self.uah.clone(),
+            ..Self::new(&self.sub, now, config).remembered(self.remember, config)
+        }
+    }
+
+    /// when `remember` is set, live for `remember_me_ttl` instead of `ttl`.
+    fn remembered(self, remember: bool, config: &SessionConfig) -> Self {
+        if !remember {
+            return self;
+        }
+        Self {
+            exp: self.iat + config.remember_me_ttl.as_secs(),
+            remember,
+            ..self
+        }
+    }
+
+    fn auth_time(&self) -> u64 {
+        self.auth_time.unwrap_or(self.iat)
+    }
+
+    fn last_active(&self) -> u64 {
+        self.last_active.unwrap_or(self.iat)
+    }
+
+    /// whether the session may still be extended at `now`: within the
+    /// absolute lifetime, and not idle for longer than the idle timeout.
+    fn refreshable(&self, now: u64, config: &SessionConfig) -> bool {
+        let idle = now.saturating_sub(self.last_active());
+        now.saturating_sub(self.auth_time()) <= config.max_lifetime.as_secs()
+            && config
+                .idle_timeout
+                .is_none_or(|timeout| idle <= timeout.as_secs())
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// bind the session to the user agent of `request`, if configured.
+    fn bind(self, request: &HttpRequest, config: &SessionConfig) -> Self {
+        Self {
+            uah: config.bind_user_agent.then(|| user_agent_hash(request)),
+            ..self
+        }
+    }
+
+    /// whether `request` comes from the user agent the session is bound to.
+    /// Unbound tokens, and any token while binding is off, always match.
+    fn bound_to(&self, request: &HttpRequest, config: &SessionConfig) -> bool {
+        match &self.uah {
+            Some(uah) if config.bind_user_agent => secure_eq(uah, &user_agent_hash(request)),
+            _ => true,
+        }
+    }
+}
+
+/// the body returned whenever a session token is issued.
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+    /// left out when the session lives in the cookie, out of reach of scripts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+    pub sub: String,
+    pub provider: String,
+    pub roles: Vec<String>,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+impl SessionResponse {
+    fn from_claims(token: String, claims: &Claims) -> Self {
+        Self {
+            token: Some(token),
+            sub: claims.sub.clone(),
+            provider: claims.provider.clone(),
+            roles: claims.roles.clone(),
+            issued_at: claims.iat,
+            expires_at: claims.exp,
+            refresh_token: None,
+        }
+    }
+
+    /// the session without its token, for requests authenticated by cookie.
+    fn without_token(self) -> Self {
+        Self {
+            token: None,
+            ..self
+        }
+    }
 }
 
-pub fn verify(token: &str, now: u64) -> bool {
-    if let Some(token) = token.verify_with_key(&*PRIVATE_KEY).ok() {
-        let token: Token<Header, Claims, _> = token;
-        let claims: &Claims = token.claims();
-        claims.iat < now && claims.exp > now
+const MSGPACK: &str = "application/msgpack";
+
+/// whether the client ranks MessagePack above json in its `Accept`.
+fn wants_msgpack(request: &HttpRequest) -> bool {
+    let Some(accept) = request.get_header::<header::Accept>() else {
+        return false;
+    };
+    accept
+        .ranked()
+        .iter()
+        .find_map(|mime| match mime.essence_str() {
+            MSGPACK | "application/x-msgpack" | "application/vnd.msgpack" => Some(true),
+            "application/json" | "application/*" | "*/*" => Some(false),
+            _ => None,
+        })
+        .unwrap_or(false)
+}
+
+/// send `body` as MessagePack to clients asking for it, as json otherwise.
+fn respond(
+    mut response: HttpResponseBuilder,
+    body: &SessionResponse,
+    request: &HttpRequest,
+) -> Result<HttpResponse, ServerError> {
+    if wants_msgpack(request) {
+        Ok(response
+            .content_type(MSGPACK)
+            .body(rmp_serde::to_vec_named(body)?))
     } else {
-        false
+        Ok(response.json(body))
+    }
+}
+
+/// json bodies of the auth endpoints only ever carry a few tokens.
+pub const JSON_LIMIT: usize = 16 * 1024;
+
+/// report bodies that do not deserialize in our error shape; anything else,
+/// like a wrong content type or an oversized body, keeps its own status.
+fn json_error(err: JsonPayloadError, _: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Deserialize(err) => ServerError::InvalidBody(err.to_string()).into(),
+        err => err.into(),
+    }
+}
+
+/// how json bodies of the auth and admin endpoints are read.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(JSON_LIMIT)
+        .error_handler(json_error)
+}
+
+/// mount the auth endpoints under `/api/auth`.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api/auth")
+            .wrap(middleware::from_fn(inflight::guard))
+            .wrap(no_store())
+            .app_data(json_config())
+            .service(login::login_handler)
+            .service(login::cookie_login_handler)
+            .service(login::logout_logging)
+            .service(tick::handler)
+            .service(refresh::handler)
+            .service(session::handler)
+            .service(sessions::handler)
+            .service(batch::service())
+            .service(jwks::handler)
+            .service(my_logins::handler),
+    );
+}
+
+/// auth responses carry tokens, no intermediary may cache or share them.
+/// They differ by the credential presented and the format asked for.
+pub fn no_store() -> middleware::DefaultHeaders {
+    middleware::DefaultHeaders::new()
+        .add((header::CACHE_CONTROL, "no-store"))
+        .add((header::PRAGMA, "no-cache"))
+        .add((header::VARY, "Accept, Authorization, Cookie"))
+}
+
+/// compare secrets without leaking through timing how much of them matched.
+/// Only the length may be learnt.
+pub fn secure_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// the session token as a cookie scripts cannot read.
+fn session_cookie(token: &str, config: &SessionConfig) -> Cookie<'static> {
+    let mut cookie = Cookie::build(SESSION_COOKIE, token.to_string())
+        .path(config.cookie_path.clone())
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .max_age(cookie::time::Duration::seconds(config.ttl.as_secs() as i64))
+        .finish();
+    if let Some(domain) = &config.cookie_domain {
+        cookie.set_domain(domain.clone());
+    }
+    cookie
+}
+
+/// like [`session_cookie`], but expiring with the token of `claims`.
+fn session_cookie_for(token: &str, claims: &Claims, config: &SessionConfig) -> Cookie<'static> {
+    let mut cookie = session_cookie(token, config);
+    cookie.set_max_age(cookie::time::Duration::seconds(
+        claims.exp.saturating_sub(claims.iat) as i64,
+    ));
+    cookie
+}
+
+fn cookie_token(request: &HttpRequest) -> Option<String> {
+    request
+        .cookie(SESSION_COOKIE)
+        .map(|cookie| cookie.value().to_string())
+}
+
+fn user_agent_hash(request: &HttpRequest) -> String {
+    let digest = Sha256::digest(login::user_agent(request));
+    digest[..16].iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn issue_token(claims: &Claims) -> Result<String, ServerError> {
+    // a token that is never valid points at a broken ttl config
+    if claims.exp <= claims.iat {
+        return Err(ServerError::Internal(format!(
+            "refuse to issue token expiring at {} before it is issued at {}",
+            claims.exp, claims.iat
+        )));
+    }
+    KEYS.read().expect("key ring poisoned").sign(claims)
+}
+
+/// why a token was not accepted by [`verify_detailed`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// malformed, or not signed by any of our keys
+    BadSignature,
+    WrongIssuer,
+    WrongAudience,
+    WrongType,
+    /// expires before it is issued, never valid
+    BadWindow,
+    /// claims to live longer than any token this deployment issues
+    TooLongLived,
+    NotYetValid,
+    Expired,
+    Revoked,
+}
+
+impl From<VerifyError> for ServerError {
+    fn from(err: VerifyError) -> Self {
+        match err {
+            VerifyError::Expired => ServerError::ExpiredToken,
+            _ => ServerError::InvalidToken,
+        }
+    }
+}
+
+pub fn verify_detailed(
+    token: &str,
+    token_type: TokenType,
+    now: u64,
+    config: &SessionConfig,
+    revocations: &RevocationStore,
+) -> Result<Claims, VerifyError> {
+    let claims = KEYS
+        .read()
+        .expect("key ring poisoned")
+        .decode(token)
+        .ok_or(VerifyError::BadSignature)?;
+    let leeway = config.leeway.as_secs();
+    if claims.iss != ISSUER {
+        Err(VerifyError::WrongIssuer)
+    } else if claims.aud != config.audience {
+        Err(VerifyError::WrongAudience)
+    } else if claims.token_type != token_type {
+        Err(VerifyError::WrongType)
+    } else if claims.exp <= claims.iat {
+        Err(VerifyError::BadWindow)
+    } else if claims.exp - claims.iat > config.max_token_lifetime(token_type) {
+        Err(VerifyError::TooLongLived)
+    } else if claims.iat > now + leeway {
+        Err(VerifyError::NotYetValid)
+    } else if claims.exp + leeway < now {
+        Err(VerifyError::Expired)
+    } else if revocations.is_revoked(&claims.jti)
+        || revocations.is_revoked_before(&claims.sub, claims.iat)
+    {
+        Err(VerifyError::Revoked)
+    } else {
+        Ok(claims)
+    }
+}
+
+pub fn verify(
+    token: &str,
+    token_type: TokenType,
+    now: u64,
+    config: &SessionConfig,
+    revocations: &RevocationStore,
+) -> Option<Claims> {
+    verify_detailed(token, token_type, now, config, revocations).ok()
+}
+
+/// the address of the client behind `request`. The forwarded-for chain is
+/// only honoured when the peer is a trusted proxy, and the ip claimed in the
+/// request body is only a fallback when no peer address is known. Anything
+/// that is not a valid ip address is dropped.
+fn client_ip(
+    request: &HttpRequest,
+    claimed: Option<&str>,
+    config: &SessionConfig,
+) -> Option<IpAddr> {
+    let peer = request.peer_addr().map(|addr| addr.ip());
+
+    if let Some(peer) = peer {
+        if !config.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+        // the right-most entry not added by one of our proxies is the client
+        let forwarded = request
+            .headers()
+            .get("X-Forwarded-For")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let client = forwarded
+            .rsplit(',')
+            .map_while(|entry| entry.trim().parse::<IpAddr>().ok())
+            .find(|ip| !config.trusted_proxies.contains(ip));
+        return Some(client.unwrap_or(peer));
+    }
+
+    claimed.and_then(|ip| ip.trim().parse().ok())
+}
+
+/// the claims of a request carrying a valid `Authorization: Bearer` token, or
+/// failing that, a valid session cookie.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub claims: Claims,
+    pub token: String,
+    /// the token came from the session cookie, not the `Authorization` header
+    pub from_cookie: bool,
+}
+
+impl AuthenticatedUser {
+    fn authenticate(request: &HttpRequest) -> Result<Self, ServerError> {
+        let (token, from_cookie) = match request.headers().get(header::AUTHORIZATION) {
+            Some(value) => (
+                value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| value.strip_prefix("Bearer "))
+                    .ok_or(ServerError::MalformedToken)?
+                    .trim()
+                    .to_string(),
+                false,
+            ),
+            None => (
+                cookie_token(request).ok_or(ServerError::MissingToken)?,
+                true,
+            ),
+        };
+        let config = request
+            .app_data::<web::Data<SessionConfig>>()
+            .ok_or_else(|| ServerError::Internal(String::from("missing session config")))?;
+        let revocations = request
+            .app_data::<web::Data<RevocationStore>>()
+            .ok_or_else(|| ServerError::Internal(String::from("missing revocation store")))?;
+
+        let clock = request
+            .app_data::<web::Data<dyn Clock>>()
+            .ok_or_else(|| ServerError::Internal(String::from("missing clock")))?;
+
+        let claims = verify(
+            &token,
+            TokenType::Access,
+            clock.now_secs(),
+            config,
+            revocations,
+        )
+        .filter(|claims| claims.bound_to(request, config))
+        .ok_or(ServerError::InvalidToken)?;
+        Ok(Self {
+            claims,
+            token,
+            from_cookie,
+        })
+    }
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::authenticate(request))
+    }
+}
+
+pub trait Role {
+    const NAME: &'static str;
+}
+
+#[derive(Debug, Clone)]
+pub struct Admin;
+
+impl Role for Admin {
+    const NAME: &'static str = "admin";
+}
+
+/// like [`AuthenticatedUser`], but also requires the session to carry role `R`.
+#[derive(Debug, Clone)]
+pub struct RequireRole<R: Role>(pub Claims, PhantomData<R>);
+
+impl<R: Role> FromRequest for RequireRole<R> {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(AuthenticatedUser::authenticate(request).and_then(|user| {
+            if user.claims.has_role(R::NAME) {
+                Ok(Self(user.claims, PhantomData))
+            } else {
+                Err(ServerError::Forbidden)
+            }
+        }))
+    }
+}
+
+/// start signing with a new key; tokens signed with the old one stay valid.
+pub fn rotate_key() -> Result<(), ServerError> {
+    KEYS.write().expect("key ring poisoned").rotate()
+}
+
+/// start signing with a new key and drop all old ones, logging everyone out.
+pub fn force_logout_all() -> Result<(), ServerError> {
+    KEYS.write().expect("key ring poisoned").replace_all()
+}
+
+mod seconds {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(value: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(value.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_secs)
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use std::time::Duration;
+
+        pub fn serialize<S: Serializer>(
+            value: &Option<Duration>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match value {
+                Some(value) => serializer.serialize_some(&value.as_secs()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Duration>, D::Error> {
+            Option::<u64>::deserialize(deserializer).map(|value| value.map(Duration::from_secs))
+        }
     }
 }
 
+/// the time handler tests run at.
+#[cfg(test)]
+pub(crate) const TEST_NOW: u64 = 1_700_000_000;
+
+#[cfg(test)]
+pub(crate) fn test_clock() -> web::Data<dyn Clock> {
+    clock::data(std::sync::Arc::new(clock::MockClock::new(TEST_NOW)))
+}
+
+/// a currently valid token for `sub`, for handler tests.
+#[cfg(test)]
+pub(crate) fn test_token(sub: &str) -> String {
+    test_token_with(sub, &SessionConfig::default())
+}
+
+/// like [`test_token`], with the roles and lifetimes of `config`.
+#[cfg(test)]
+pub(crate) fn test_token_with(sub: &str, config: &SessionConfig) -> String {
+    issue_token(&Claims::new(sub, TEST_NOW - 1, config)).unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use jwt::SignWithKey;
+    use actix_web::{body::MessageBody, test::TestRequest};
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn response_follows_accept() {
+        let claims = Claims::new("user", TEST_NOW, &SessionConfig::default());
+        let body = SessionResponse::from_claims(String::from("token"), &claims);
+        let send = |accept: Option<&str>| {
+            let mut request = TestRequest::default();
+            if let Some(accept) = accept {
+                request = request.insert_header((header::ACCEPT, accept));
+            }
+            let response = respond(HttpResponse::Ok(), &body, &request.to_http_request()).unwrap();
+            let content_type = response.headers().get(header::CONTENT_TYPE).cloned();
+            let bytes = response.into_body().try_into_bytes().unwrap();
+            (content_type.unwrap(), bytes)
+        };
+
+        let (content_type, json) = send(None);
+        assert_eq!(content_type, "application/json");
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["sub"], "user");
+
+        let (content_type, msgpack) = send(Some("application/msgpack"));
+        assert_eq!(content_type, "application/msgpack");
+        let msgpack: Value = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(msgpack, json);
+
+        // the first acceptable type in order of preference wins
+        let (content_type, _) = send(Some("application/json, application/msgpack;q=0.5"));
+        assert_eq!(content_type, "application/json");
+        let (content_type, _) = send(Some("text/html, application/msgpack;q=0.9, */*;q=0.1"));
+        assert_eq!(content_type, "application/msgpack");
+    }
 
     #[test]
     fn verify_accepts_valid_window() {
-        let claims = Claims { iat: 100, exp: 200 };
-        let token = claims.clone().sign_with_key(&*PRIVATE_KEY).unwrap();
+        let claims = Claims {
+            exp: 200,
+            ..Claims::new("user", 100, &SessionConfig::default())
+        };
+        let token = issue_token(&claims).unwrap();
+        let revocations = RevocationStore::default();
 
-        assert!(verify(&token, 150));
-        assert!(!verify(&token, 90));
-        assert!(!verify(&token, 250));
+        let strict = SessionConfig {
+            leeway: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(verify(&token, TokenType::Access, 150, &strict, &revocations).is_some());
+        assert!(verify(&token, TokenType::Access, 90, &strict, &revocations).is_none());
+        assert!(verify(&token, TokenType::Access, 250, &strict, &revocations).is_none());
+        assert!(verify(&token, TokenType::Access, 100, &strict, &revocations).is_some());
+        assert!(verify(&token, TokenType::Access, 200, &strict, &revocations).is_some());
+
+        let config = SessionConfig::default();
+        assert!(verify(&token, TokenType::Access, 70, &config, &revocations).is_some());
+        assert!(verify(&token, TokenType::Access, 69, &config, &revocations).is_none());
+        assert!(verify(&token, TokenType::Access, 230, &config, &revocations).is_some());
+        assert!(verify(&token, TokenType::Access, 231, &config, &revocations).is_none());
+    }
+
+    #[test]
+    fn verify_detailed_names_the_reason() {
+        let config = SessionConfig::default();
+        let revocations = RevocationStore::default();
+        let claims = Claims::new("user", TEST_NOW, &config);
+        let check = |claims: &Claims| {
+            let token = KEYS.read().unwrap().sign(claims).unwrap();
+            verify_detailed(&token, TokenType::Access, TEST_NOW, &config, &revocations)
+        };
+
+        assert!(check(&claims).is_ok());
+        assert_eq!(
+            verify_detailed(
+                "invalid",
+                TokenType::Access,
+                TEST_NOW,
+                &config,
+                &revocations
+            )
+            .map(|_| ()),
+            Err(VerifyError::BadSignature)
+        );
+        let cases = [
+            (
+                Claims {
+                    iss: String::from("other"),
+                    ..claims.clone()
+                },
+                VerifyError::WrongIssuer,
+            ),
+            (
+                Claims {
+                    aud: String::from("other"),
+                    ..claims.clone()
+                },
+                VerifyError::WrongAudience,
+            ),
+            (claims.to_refresh_token(&config), VerifyError::WrongType),
+            (
+                Claims {
+                    exp: claims.iat,
+                    ..claims.clone()
+                },
+                VerifyError::BadWindow,
+            ),
+            (
+                Claims::new("user", TEST_NOW + 3600, &config),
+                VerifyError::NotYetValid,
+            ),
+            (
+                Claims::new("user", TEST_NOW - 7200, &config),
+                VerifyError::Expired,
+            ),
+        ];
+        for (claims, err) in cases {
+            assert_eq!(check(&claims).map(|_| ()), Err(err));
+        }
+
+        revocations.revoke(&claims.jti, claims.exp, TEST_NOW, &config);
+        assert_eq!(check(&claims).map(|_| ()), Err(VerifyError::Revoked));
+    }
+
+    #[test]
+    fn verify_rejects_over_long_lived_token() {
+        let config = SessionConfig::default();
+        let revocations = RevocationStore::default();
+        let claims = Claims::new("user", TEST_NOW, &config);
+        let lived = |lifetime: u64, config: &SessionConfig| {
+            let token = issue_token(&Claims {
+                exp: claims.iat + lifetime,
+                ..claims.clone()
+            })
+            .unwrap();
+            verify_detailed(&token, TokenType::Access, TEST_NOW, config, &revocations).map(|_| ())
+        };
+
+        // remembered sessions are the longest lived access tokens
+        let longest = (config.remember_me_ttl + config.leeway).as_secs();
+        assert_eq!(lived(longest, &config), Ok(()));
+        assert_eq!(lived(longest + 1, &config), Err(VerifyError::TooLongLived));
+        assert_eq!(
+            lived(100 * 365 * 86400, &config),
+            Err(VerifyError::TooLongLived)
+        );
+
+        let strict = SessionConfig {
+            max_token_lifetime: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        assert_eq!(lived(600, &strict), Ok(()));
+        assert_eq!(lived(601, &strict), Err(VerifyError::TooLongLived));
+
+        // refresh tokens are held to the refresh lifetime
+        let refresh = issue_token(&claims.to_refresh_token(&config)).unwrap();
+        assert!(
+            verify(
+                &refresh,
+                TokenType::Refresh,
+                TEST_NOW,
+                &config,
+                &revocations
+            )
+            .is_some()
+        );
     }
 
     #[test]
     fn verify_rejects_invalid_token() {
-        assert!(!verify("invalid", 100));
+        let config = SessionConfig::default();
+        assert!(
+            verify(
+                "invalid",
+                TokenType::Access,
+                100,
+                &config,
+                &RevocationStore::default()
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn verify_rejects_revoked_token() {
+        let config = SessionConfig::default();
+        let claims = Claims::new("user", 100, &config);
+        let token = issue_token(&claims).unwrap();
+        let revocations = RevocationStore::default();
+        revocations.revoke(&claims.jti, claims.exp, 100, &config);
+
+        assert!(verify(&token, TokenType::Access, 150, &config, &revocations).is_none());
+    }
+
+    #[test]
+    fn verify_rejects_token_older_than_watermark() {
+        let config = SessionConfig::default();
+        let old = issue_token(&Claims::new("user", 100, &config)).unwrap();
+        let revocations = RevocationStore::default();
+        assert!(verify(&old, TokenType::Access, 150, &config, &revocations).is_some());
+
+        revocations.revoke_all("user", 150);
+        let new = issue_token(&Claims::new("user", 160, &config)).unwrap();
+        assert!(verify(&old, TokenType::Access, 170, &config, &revocations).is_none());
+        assert!(verify(&new, TokenType::Access, 170, &config, &revocations).is_some());
+    }
+
+    #[test]
+    fn login_in_the_second_of_a_revoke_all_is_kept() {
+        let config = SessionConfig::default();
+        let revocations = RevocationStore::default();
+        revocations.revoke_all("user", 150);
+
+        let token = issue_token(&Claims::new("user", 150, &config)).unwrap();
+        assert!(verify(&token, TokenType::Access, 150, &config, &revocations).is_some());
+    }
+
+    #[test]
+    fn deployment_claim_is_opt_in() {
+        let config = SessionConfig {
+            deployment_id: String::from("staging"),
+            ..SessionConfig::default()
+        };
+        let token = issue_token(&Claims::new("user", TEST_NOW, &config)).unwrap();
+        let claims = verify(
+            &token,
+            TokenType::Access,
+            TEST_NOW,
+            &config,
+            &RevocationStore::default(),
+        );
+        assert_eq!(claims.unwrap().deployment, None);
+
+        let config = SessionConfig {
+            deployment_claim: true,
+            ..config
+        };
+        let claims = Claims::new("user", TEST_NOW, &config).refresh(TEST_NOW + 60, &config);
+        let token = issue_token(&claims).unwrap();
+        let claims = verify(
+            &token,
+            TokenType::Access,
+            TEST_NOW + 60,
+            &config,
+            &RevocationStore::default(),
+        );
+        assert_eq!(claims.unwrap().deployment.as_deref(), Some("staging"));
+    }
+
+    #[test]
+    fn verify_requires_issuer_and_audience() {
+        let config = SessionConfig::default();
+        let claims = Claims::new("user", 100, &config);
+        let revocations = RevocationStore::default();
+
+        let wrong_iss = issue_token(&Claims {
+            iss: String::from("elsewhere"),
+            ..claims.clone()
+        })
+        .unwrap();
+        assert!(verify(&wrong_iss, TokenType::Access, 150, &config, &revocations).is_none());
+
+        let token = issue_token(&claims).unwrap();
+        let staging = SessionConfig {
+            audience: String::from("staging"),
+            ..Default::default()
+        };
+        assert!(verify(&token, TokenType::Access, 150, &staging, &revocations).is_none());
+        assert!(verify(&token, TokenType::Access, 150, &config, &revocations).is_some());
+    }
+
+    #[test]
+    fn secure_eq_matches_equality() {
+        assert!(secure_eq("secret", "secret"));
+        assert!(secure_eq("", ""));
+        assert!(!secure_eq("secret", "secreT"));
+        assert!(!secure_eq("secret", "secret!"));
+        assert!(!secure_eq("", "secret"));
+    }
+
+    #[test]
+    fn issue_token_rejects_empty_window() {
+        let claims = Claims::new("user", 100, &SessionConfig::default());
+        for exp in [100, 50] {
+            let claims = Claims {
+                exp,
+                ..claims.clone()
+            };
+            assert!(issue_token(&claims).is_err());
+        }
+    }
+
+    #[test]
+    fn verify_rejects_empty_window() {
+        let config = SessionConfig::default();
+        let revocations = RevocationStore::default();
+        let claims = Claims::new("user", 100, &config);
+        for exp in [100, 50] {
+            // bypass issue_token, as a token minted elsewhere would
+            let token = KEYS
+                .read()
+                .unwrap()
+                .sign(&Claims {
+                    exp,
+                    ..claims.clone()
+                })
+                .unwrap();
+            assert!(verify(&token, TokenType::Access, 100, &config, &revocations).is_none());
+        }
+    }
+
+    #[test]
+    fn issue_token_respects_ttl() {
+        let config = SessionConfig {
+            ttl: Duration::from_secs(15 * 60),
+            ..Default::default()
+        };
+        let token = issue_token(&Claims::new("user", 1000, &config)).unwrap();
+
+        let claims = KEYS.read().unwrap().decode(&token).unwrap();
+        assert_eq!(claims.exp - claims.iat, 900);
+    }
+
+    #[test]
+    fn session_config_defaults_to_one_hour() {
+        let config: SessionConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.ttl, Duration::from_secs(3600));
+
+        let config: SessionConfig = serde_json::from_str(r#"{"ttl":900}"#).unwrap();
+        assert_eq!(config.ttl, Duration::from_secs(900));
+    }
+
+    #[actix_web::test]
+    async fn authenticated_user_extracts_claims() {
+        let claims = Claims::new("user", TEST_NOW - 1, &SessionConfig::default());
+        let request = actix_web::test::TestRequest::default()
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", issue_token(&claims).unwrap()),
+            ))
+            .app_data(web::Data::new(SessionConfig::default()))
+            .app_data(web::Data::new(RevocationStore::default()))
+            .app_data(test_clock())
+            .to_http_request();
+
+        let user = AuthenticatedUser::extract(&request).await.unwrap();
+        assert_eq!(user.claims.sub, "user");
+        assert_eq!(user.claims.jti, claims.jti);
+    }
+
+    #[actix_web::test]
+    async fn authenticated_user_accepts_session_cookie() {
+        let claims = Claims::new("user", TEST_NOW - 1, &SessionConfig::default());
+        let cookie = session_cookie(&issue_token(&claims).unwrap(), &SessionConfig::default());
+        let request = actix_web::test::TestRequest::default()
+            .cookie(cookie)
+            .app_data(web::Data::new(SessionConfig::default()))
+            .app_data(web::Data::new(RevocationStore::default()))
+            .app_data(test_clock())
+            .to_http_request();
+
+        let user = AuthenticatedUser::extract(&request).await.unwrap();
+        assert_eq!(user.claims.jti, claims.jti);
+    }
+
+    #[test]
+    fn session_cookie_is_locked_down() {
+        let cookie = session_cookie("token", &SessionConfig::default());
+        assert_eq!(cookie.name(), SESSION_COOKIE);
+        assert_eq!(cookie.secure(), Some(true));
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Strict));
+        assert_eq!(cookie.max_age(), Some(cookie::time::Duration::hours(1)));
+        assert_eq!(cookie.path(), Some("/api"));
+        assert_eq!(cookie.domain(), None);
+    }
+
+    #[test]
+    fn session_cookie_takes_configured_scope() {
+        let config = SessionConfig {
+            cookie_domain: Some(String::from("mercuryland.dev")),
+            cookie_path: String::from("/"),
+            ..Default::default()
+        };
+        let header = session_cookie("token", &config).to_string();
+        assert!(header.contains("; Domain=mercuryland.dev"));
+        assert!(header.contains("; Path=/;") || header.ends_with("; Path=/"));
+
+        let mut removal = session_cookie("", &config);
+        removal.make_removal();
+        assert_eq!(removal.domain(), Some("mercuryland.dev"));
+        assert_eq!(removal.path(), Some("/"));
+    }
+
+    #[actix_web::test]
+    async fn authenticated_user_rejects_bad_requests() {
+        use actix_web::{ResponseError, http::StatusCode};
+
+        let extract = |value: Option<&str>| {
+            let mut request = actix_web::test::TestRequest::default()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock());
+            if let Some(value) = value {
+                request = request.insert_header((header::AUTHORIZATION, value));
+            }
+            AuthenticatedUser::authenticate(&request.to_http_request()).unwrap_err()
+        };
+
+        let err = extract(None);
+        assert!(matches!(err, ServerError::MissingToken));
+        let err = extract(Some("Basic dXNlcjpwYXNz"));
+        assert!(matches!(err, ServerError::MalformedToken));
+        let err = extract(Some("Bearer garbage"));
+        assert!(matches!(err, ServerError::InvalidToken));
+        assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn user_agent_binding_is_checked() {
+        use actix_web::test::TestRequest;
+
+        let config = SessionConfig {
+            bind_user_agent: true,
+            ..SessionConfig::default()
+        };
+        let request = |user_agent: &str, config: &SessionConfig, token: &str| {
+            TestRequest::default()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .insert_header((header::USER_AGENT, user_agent))
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .to_http_request()
+        };
+        let login = request("firefox", &config, "");
+        let claims = Claims::new("user", TEST_NOW - 1, &config).bind(&login, &config);
+        assert!(claims.uah.is_some());
+        let token = issue_token(&claims.refresh(TEST_NOW, &config)).unwrap();
+
+        assert!(AuthenticatedUser::authenticate(&request("firefox", &config, &token)).is_ok());
+        let err = AuthenticatedUser::authenticate(&request("curl", &config, &token)).unwrap_err();
+        assert!(matches!(err, ServerError::InvalidToken));
+
+        // turning binding off relaxes tokens issued while it was on
+        let off = SessionConfig::default();
+        assert!(AuthenticatedUser::authenticate(&request("curl", &off, &token)).is_ok());
+        assert!(
+            Claims::new("user", TEST_NOW, &off)
+                .bind(&login, &off)
+                .uah
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn remember_me_selects_lifetime() {
+        let config = SessionConfig::default();
+        let lifetime = |claims: &Claims| claims.exp - claims.iat;
+
+        let short = Claims::new("user", TEST_NOW, &config).remembered(false, &config);
+        assert_eq!(lifetime(&short), config.ttl.as_secs());
+        let long = Claims::new("user", TEST_NOW, &config).remembered(true, &config);
+        assert_eq!(lifetime(&long), config.remember_me_ttl.as_secs());
+
+        // the choice survives a tick, and the long token still verifies
+        let ticked = long.refresh(TEST_NOW + 60, &config);
+        assert_eq!(lifetime(&ticked), config.remember_me_ttl.as_secs());
+        let token = issue_token(&ticked).unwrap();
+        let revocations = RevocationStore::default();
+        let verified = verify(
+            &token,
+            TokenType::Access,
+            TEST_NOW + 60,
+            &config,
+            &revocations,
+        );
+        assert!(verified.unwrap().remember);
+        let ticked = short.refresh(TEST_NOW + 60, &config);
+        assert_eq!(lifetime(&ticked), config.ttl.as_secs());
+
+        let cookie = session_cookie_for("token", &long, &config);
+        assert_eq!(
+            cookie.max_age(),
+            Some(cookie::time::Duration::seconds(
+                config.remember_me_ttl.as_secs() as i64
+            ))
+        );
+    }
+
+    #[test]
+    fn provider_round_trips() {
+        let config = SessionConfig::default();
+        let claims = Claims::new("user", TEST_NOW, &config).refresh(TEST_NOW + 60, &config);
+        let token = issue_token(&claims).unwrap();
+        let claims = verify(
+            &token,
+            TokenType::Access,
+            TEST_NOW + 60,
+            &config,
+            &RevocationStore::default(),
+        );
+        assert_eq!(claims.unwrap().provider, PASSWORD_PROVIDER);
+
+        let legacy: Claims =
+            serde_json::from_value(serde_json::json!({ "iat": 100, "exp": 200 })).unwrap();
+        assert_eq!(legacy.provider, PASSWORD_PROVIDER);
+    }
+
+    #[test]
+    fn roles_are_granted_from_config() {
+        let config = SessionConfig {
+            roles: HashMap::from([(String::from("admin"), vec![String::from("admin")])]),
+            ..Default::default()
+        };
+
+        assert!(Claims::new("admin", 100, &config).has_role("admin"));
+        assert!(!Claims::new("user", 100, &config).has_role("admin"));
+    }
+
+    #[actix_web::test]
+    async fn require_role_rejects_missing_role() {
+        use actix_web::{ResponseError, http::StatusCode};
+
+        let config = SessionConfig {
+            roles: HashMap::from([(String::from("admin"), vec![String::from("admin")])]),
+            ..Default::default()
+        };
+        let extract = |sub: &str| {
+            let claims = Claims::new(sub, TEST_NOW - 1, &config);
+            actix_web::test::TestRequest::default()
+                .insert_header((
+                    header::AUTHORIZATION,
+                    format!("Bearer {}", issue_token(&claims).unwrap()),
+                ))
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .to_http_request()
+        };
+
+        let user = RequireRole::<Admin>::extract(&extract("admin"))
+            .await
+            .unwrap();
+        assert_eq!(user.0.sub, "admin");
+        let err = RequireRole::<Admin>::extract(&extract("user"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn client_ip_drops_invalid_claims() {
+        let config = SessionConfig::default();
+        let request = actix_web::test::TestRequest::default().to_http_request();
+
+        assert_eq!(
+            client_ip(&request, Some("127.0.0.1\n[Login] forged"), &config),
+            None
+        );
+        assert_eq!(client_ip(&request, Some("not an ip"), &config), None);
+        assert_eq!(
+            client_ip(&request, Some("203.0.113.7"), &config),
+            Some("203.0.113.7".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(&request, Some("2001:db8::1"), &config),
+            Some("2001:db8::1".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn client_ip_prefers_peer_address() {
+        let proxy: IpAddr = "10.0.0.1".parse().unwrap();
+        let config = SessionConfig {
+            trusted_proxies: vec![proxy],
+            ..Default::default()
+        };
+
+        // untrusted peers cannot forward a spoofed address
+        let request = actix_web::test::TestRequest::default()
+            .peer_addr("198.51.100.2:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "203.0.113.7"))
+            .to_http_request();
+        assert_eq!(
+            client_ip(&request, Some("192.0.2.1"), &config),
+            Some("198.51.100.2".parse().unwrap())
+        );
+
+        let request = actix_web::test::TestRequest::default()
+            .peer_addr("10.0.0.1:1234".parse().unwrap())
+            .insert_header(("X-Forwarded-For", "192.0.2.1, 203.0.113.7, 10.0.0.1"))
+            .to_http_request();
+        assert_eq!(
+            client_ip(&request, None, &config),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn idle_timeout_is_parsed_from_config() {
+        let config: SessionConfig = serde_json::from_str(r#"{"idle_timeout":600}"#).unwrap();
+        assert_eq!(config.idle_timeout, Some(Duration::from_secs(600)));
+        assert_eq!(SessionConfig::default().idle_timeout, None);
+    }
+
+    #[test]
+    fn auth_time_defaults_to_iat() {
+        let claims: Claims = serde_json::from_str(r#"{"iat":100,"exp":200}"#).unwrap();
+        assert_eq!(claims.auth_time(), 100);
+
+        let config = SessionConfig::default();
+        let claims = claims.refresh(150, &config);
+        assert_eq!(claims.iat, 150);
+        assert_eq!(claims.auth_time(), 100);
     }
 }
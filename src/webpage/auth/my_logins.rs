@@ -0,0 +1,110 @@
+use super::AuthenticatedUser;
+use crate::{
+    database::{self, login_history::LoginEvent},
+    error::ServerError,
+    webpage::admin::logins::{Request, paginate},
+};
+use actix_web::{HttpResponse, Responder, get, web};
+
+/// the login history of the presented session's user, and nobody else's.
+#[get("/my-logins")]
+pub async fn handler(
+    user: AuthenticatedUser,
+    request: web::Query<Request>,
+) -> Result<impl Responder, ServerError> {
+    let limit = request.limit();
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let events = LoginEvent::page_of(
+        (&user.claims.provider, &user.claims.sub),
+        request.since,
+        request.cursor,
+        limit + 1,
+        &transaction,
+    )?;
+
+    Ok(HttpResponse::Ok().json(paginate(events, limit)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        SessionConfig, configure, revocation::RevocationStore, test_clock, test_token,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+    use chrono::Utc;
+    use serde_json::Value;
+
+    #[actix_web::test]
+    async fn anonymous_request_is_unauthorized() {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+
+        let request = TestRequest::get().uri("/api/auth/my-logins").to_request();
+        assert_eq!(
+            call_service(&app, request).await.status(),
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn only_own_logins_are_listed() -> Result<(), ServerError> {
+        let mut connection = database::test_database()?;
+        let transaction = connection.transaction()?;
+        for (provider, sub) in [
+            ("password", "user"),
+            ("password", "other"),
+            ("google", "user"),
+            ("password", "user"),
+        ] {
+            LoginEvent {
+                id: 0,
+                sub: String::from(sub),
+                provider: String::from(provider),
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                created_at: Utc::now(),
+            }
+            .insert(&transaction)?;
+        }
+        transaction.commit()?;
+
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/api/auth/my-logins")
+            .insert_header((
+                header::AUTHORIZATION,
+                format!("Bearer {}", test_token("user")),
+            ))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        let events = body["events"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        for event in events {
+            assert_eq!(event["provider"], "password");
+            assert_eq!(event["sub"], "user");
+        }
+        Ok(())
+    }
+}
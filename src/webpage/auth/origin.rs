@@ -0,0 +1,146 @@
+use super::SessionConfig;
+use crate::error::ServerError;
+use actix_web::{FromRequest, HttpRequest, dev::Payload, http::header, web};
+use std::future::{Ready, ready};
+
+/// rejects cross-site submissions: when `allowed_origins` is configured, the
+/// `Origin` header, or failing that the origin of the `Referer`, has to be
+/// one of them. Requests carrying neither, which browsers never send for a
+/// cross-site post, are let through for non-browser clients.
+#[derive(Debug, Clone)]
+pub struct SameOrigin;
+
+/// `scheme://host[:port]` of a url.
+fn origin_of(url: &str) -> Option<&str> {
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    Some(&url[..scheme.len() + "://".len() + host])
+}
+
+impl SameOrigin {
+    fn check(request: &HttpRequest) -> Result<Self, ServerError> {
+        let config = request
+            .app_data::<web::Data<SessionConfig>>()
+            .ok_or_else(|| ServerError::Internal(String::from("missing session config")))?;
+        if config.allowed_origins.is_empty() {
+            return Ok(Self);
+        }
+
+        let headers = request.headers();
+        let origin = match headers.get(header::ORIGIN) {
+            Some(origin) => Some(origin.to_str().ok()),
+            None => headers
+                .get(header::REFERER)
+                .map(|referer| referer.to_str().ok().and_then(origin_of)),
+        };
+        match origin {
+            None => Ok(Self),
+            Some(Some(origin))
+                if config
+                    .allowed_origins
+                    .iter()
+                    .any(|allowed| allowed == origin) =>
+            {
+                Ok(Self)
+            }
+            Some(origin) => {
+                log::warn!("rejected request from origin {:?}", origin);
+                Err(ServerError::Forbidden)
+            }
+        }
+    }
+}
+
+impl FromRequest for SameOrigin {
+    type Error = ServerError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Self::check(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        configure, revocation::RevocationStore, test_clock, tracker::SessionTracker,
+    };
+    use actix_web::{
+        App,
+        http::StatusCode,
+        test::{TestRequest, call_service, init_service},
+    };
+    use serde_json::json;
+
+    async fn logout(
+        config: SessionConfig,
+        header: Option<(header::HeaderName, &str)>,
+    ) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let mut request = TestRequest::post()
+            .uri("/api/auth/logout")
+            .set_json(json!({ "username": "user" }));
+        if let Some(header) = header {
+            request = request.insert_header(header);
+        }
+        call_service(&app, request.to_request()).await.status()
+    }
+
+    fn guarded() -> SessionConfig {
+        SessionConfig {
+            allowed_origins: vec![String::from("https://mercuryland.pp.ua")],
+            ..SessionConfig::default()
+        }
+    }
+
+    #[test]
+    fn origin_is_cut_from_url() {
+        assert_eq!(
+            origin_of("https://mercuryland.pp.ua/wheel?id=1"),
+            Some("https://mercuryland.pp.ua")
+        );
+        assert_eq!(
+            origin_of("http://localhost:5173"),
+            Some("http://localhost:5173")
+        );
+        assert_eq!(origin_of("not a url"), None);
+    }
+
+    #[actix_web::test]
+    async fn allowed_origin_passes() {
+        let origin = (header::ORIGIN, "https://mercuryland.pp.ua");
+        assert_eq!(logout(guarded(), Some(origin)).await, StatusCode::OK);
+
+        let referer = (header::REFERER, "https://mercuryland.pp.ua/login");
+        assert_eq!(logout(guarded(), Some(referer)).await, StatusCode::OK);
+        assert_eq!(logout(guarded(), None).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn foreign_origin_is_rejected() {
+        let origin = (header::ORIGIN, "https://evil.example");
+        assert_eq!(logout(guarded(), Some(origin)).await, StatusCode::FORBIDDEN);
+
+        let referer = (header::REFERER, "https://mercuryland.pp.ua.evil.example/");
+        assert_eq!(
+            logout(guarded(), Some(referer)).await,
+            StatusCode::FORBIDDEN
+        );
+
+        // the check is off unless origins are configured
+        let origin = (header::ORIGIN, "https://evil.example");
+        assert_eq!(
+            logout(SessionConfig::default(), Some(origin)).await,
+            StatusCode::OK
+        );
+    }
+}
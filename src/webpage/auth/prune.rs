@@ -0,0 +1,111 @@
+use super::{SessionConfig, clock::Clock};
+use actix_web::web;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// an in-memory store whose entries outlive their use, swept periodically
+/// so it does not grow over a long uptime.
+pub trait Prunable: Send + Sync {
+    /// drop every entry that has no effect any more at `now`.
+    fn prune(&self, now: u64, config: &SessionConfig);
+}
+
+/// every registered store, each swept on every tick.
+#[derive(Default)]
+pub struct Maintenance(Vec<Arc<dyn Prunable>>);
+
+impl Maintenance {
+    pub fn with<P: Prunable + 'static>(mut self, store: web::Data<P>) -> Self {
+        self.0.push(store.into_inner());
+        self
+    }
+
+    pub fn sweep(&self, now: u64, config: &SessionConfig) {
+        for store in &self.0 {
+            store.prune(now, config);
+        }
+    }
+
+    /// sweep every `config.prune_interval`, for as long as the server runs.
+    pub fn spawn(
+        self,
+        clock: web::Data<dyn Clock>,
+        config: web::Data<SessionConfig>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.prune_interval);
+            loop {
+                interval.tick().await;
+                self.sweep(clock.now_secs(), &config);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        Claims,
+        debounce::{LoginDebounce, Recent},
+        lockout::LoginLockout,
+        revocation::RevocationStore,
+        tracker::{SessionInfo, SessionTracker},
+    };
+    use std::time::Duration;
+
+    fn session(sub: &str, expires_at: u64) -> SessionInfo {
+        SessionInfo {
+            sub: sub.to_string(),
+            issued_at: 0,
+            last_active: 0,
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            jti: String::new(),
+            expires_at,
+        }
+    }
+
+    #[test]
+    fn sweep_drops_expired_entries_only() {
+        let config = SessionConfig {
+            login_debounce: Some(Duration::from_secs(5)),
+            ..Default::default()
+        };
+        let revocations = web::Data::new(RevocationStore::default());
+        let tracker = web::Data::new(SessionTracker::default());
+        let lockout = web::Data::new(LoginLockout::default());
+        let debounce = web::Data::new(LoginDebounce::default());
+        let maintenance = Maintenance::default()
+            .with(revocations.clone())
+            .with(tracker.clone())
+            .with(lockout.clone())
+            .with(debounce.clone());
+
+        let now = 1000;
+        revocations.revoke("expired", 150, 100, &config);
+        revocations.revoke("live", 2000, 100, &config);
+        tracker.start("expired", session("old", 150), 100);
+        tracker.start("live", session("new", 2000), 100);
+        lockout.failed("old", now - config.lockout_window.as_secs() - 1, &config);
+        lockout.failed("new", now - 10, &config);
+        for (sub, at) in [("old", 100), ("new", now - 2)] {
+            debounce.remember(
+                Recent {
+                    claims: Claims::new(sub, at, &config),
+                    token: String::new(),
+                    refresh_token: String::new(),
+                },
+                &config,
+            );
+        }
+
+        maintenance.sweep(now, &config);
+        assert!(!revocations.is_revoked("expired"));
+        assert!(revocations.is_revoked("live"));
+        assert_eq!(tracker.end_all("old"), 0);
+        assert_eq!(tracker.end_all("new"), 1);
+        assert_eq!(lockout.len(), 1);
+        assert_eq!(debounce.len(), 1);
+    }
+}
@@ -0,0 +1,340 @@
+use super::{
+    Claims, SessionConfig, SessionResponse, TokenType,
+    clock::Clock,
+    issue_token,
+    origin::SameOrigin,
+    respond,
+    revocation::RevocationStore,
+    tick::{INVALID_SESSION, SESSION_EXPIRED, forbidden},
+    tracker::SessionTracker,
+    verify,
+};
+use crate::{error::ServerError, webpage::metrics::METRICS};
+use actix_web::{HttpRequest, HttpResponse, post, web};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Request {
+    refresh_token: String,
+}
+
+/// exchange a refresh token for a new access token.
+#[post("/refresh")]
+pub async fn handler(
+    http: HttpRequest,
+    _: SameOrigin,
+    request: web::Json<Request>,
+    config: web::Data<SessionConfig>,
+    revocations: web::Data<RevocationStore>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+) -> Result<HttpResponse, ServerError> {
+    let now = clock.now_secs();
+
+    let Some(claims) = verify(
+        &request.refresh_token,
+        TokenType::Refresh,
+        now,
+        &config,
+        &revocations,
+    ) else {
+        return Ok(forbidden(INVALID_SESSION));
+    };
+    if !claims.bound_to(&http, &config) {
+        log::warn!(
+            "refresh token of session {} presented by another user agent",
+            claims.sid
+        );
+        return Ok(forbidden(INVALID_SESSION));
+    }
+    // the refresh token is only issued at login, how long the session has
+    // been idle is known to the tracker
+    let claims = Claims {
+        last_active: tracker.last_active(&claims.sid).or(claims.last_active),
+        ..claims
+    };
+    if !claims.refreshable(now, &config) {
+        return Ok(forbidden(SESSION_EXPIRED));
+    }
+
+    let claims = claims.refresh(now, &config);
+    let token = issue_token(&claims)?;
+    tracker.touch(&claims.sid, &claims.jti, now, claims.exp);
+    METRICS.tick_refreshed();
+    respond(
+        HttpResponse::Ok(),
+        &SessionResponse::from_claims(token, &claims),
+        &http,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        TEST_NOW,
+        clock::{self, MockClock},
+        configure, test_clock, test_token,
+        tracker::SessionInfo,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test,
+    };
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// a refresh token of a session `tracker` knows about.
+    fn refresh_token(tracker: &SessionTracker) -> String {
+        let config = SessionConfig::default();
+        track(
+            tracker,
+            Claims::new("user", TEST_NOW - 1, &config).to_refresh_token(&config),
+        )
+    }
+
+    fn track(tracker: &SessionTracker, claims: Claims) -> String {
+        tracker.start(
+            &claims.sid,
+            SessionInfo {
+                sub: claims.sub.clone(),
+                issued_at: claims.iat,
+                last_active: claims.iat,
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                jti: claims.jti.clone(),
+                expires_at: claims.exp,
+            },
+            claims.iat,
+        );
+        issue_token(&claims).unwrap()
+    }
+
+    async fn post(
+        tracker: &web::Data<SessionTracker>,
+        uri: &str,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        post_with(
+            SessionConfig::default(),
+            tracker,
+            &Default::default(),
+            uri,
+            body,
+        )
+        .await
+    }
+
+    async fn post_with(
+        config: SessionConfig,
+        tracker: &web::Data<SessionTracker>,
+        revocations: &web::Data<RevocationStore>,
+        uri: &str,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(revocations.clone())
+                .app_data(test_clock())
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        let request = test::TestRequest::post()
+            .uri(uri)
+            .set_json(body)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    #[actix_web::test]
+    async fn refresh_token_mints_access_token() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let (status, body) = post(
+            &tracker,
+            "/api/auth/refresh",
+            json!({ "refresh_token": refresh_token(&tracker) }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let access = body["token"].as_str().unwrap().to_string();
+        let (status, _) = post(&tracker, "/api/auth/tick", json!({ "token": access })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn token_types_are_not_interchangeable() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let (status, _) = post(
+            &tracker,
+            "/api/auth/tick",
+            json!({ "token": refresh_token(&tracker) }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, body) = post(
+            &tracker,
+            "/api/auth/refresh",
+            json!({ "refresh_token": test_token("user") }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], INVALID_SESSION);
+    }
+
+    #[actix_web::test]
+    async fn revoked_session_is_not_refreshed() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let revocations = web::Data::new(RevocationStore::default());
+        let token = refresh_token(&tracker);
+        // what an admin revoke or a logout with `all` leaves behind
+        revocations.revoke_all("user", TEST_NOW);
+        tracker.end_all("user");
+
+        let (status, body) = post_with(
+            SessionConfig::default(),
+            &tracker,
+            &revocations,
+            "/api/auth/refresh",
+            json!({ "refresh_token": token }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], INVALID_SESSION);
+    }
+
+    #[actix_web::test]
+    async fn untracked_session_is_refreshed() {
+        // sessions outlive a restart, which the tracker does not
+        let config = SessionConfig::default();
+        let claims = Claims::new("user", TEST_NOW - 1, &config).to_refresh_token(&config);
+        let tracker = web::Data::new(SessionTracker::default());
+
+        let (status, body) = post(
+            &tracker,
+            "/api/auth/refresh",
+            json!({ "refresh_token": issue_token(&claims).unwrap() }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["token"].is_string());
+    }
+
+    #[actix_web::test]
+    async fn idle_session_is_not_refreshed() {
+        let config = SessionConfig {
+            idle_timeout: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        let tracker = web::Data::new(SessionTracker::default());
+        // logged in long ago, last seen at `last_active`
+        let token = |last_active: u64| {
+            let claims = Claims::new("user", TEST_NOW - 1000, &config).to_refresh_token(&config);
+            let token = track(&tracker, claims.clone());
+            tracker.touch(&claims.sid, &claims.jti, last_active, claims.exp);
+            token
+        };
+
+        let (status, body) = post_with(
+            config.clone(),
+            &tracker,
+            &Default::default(),
+            "/api/auth/refresh",
+            json!({ "refresh_token": token(TEST_NOW - 660) }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], SESSION_EXPIRED);
+
+        let (status, _) = post_with(
+            config.clone(),
+            &tracker,
+            &Default::default(),
+            "/api/auth/refresh",
+            json!({ "refresh_token": token(TEST_NOW - 540) }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn refreshing_session_outlasts_idle_timeout() {
+        let config = SessionConfig {
+            idle_timeout: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        let clock = Arc::new(MockClock::new(TEST_NOW));
+        let tracker = web::Data::new(SessionTracker::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(clock::data(clock.clone()))
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        let token = track(
+            &tracker,
+            Claims::new("user", TEST_NOW, &config).to_refresh_token(&config),
+        );
+
+        // well past the idle timeout since login, never once idle for it
+        for _ in 0..4 {
+            clock.advance(500);
+            let request = test::TestRequest::post()
+                .uri("/api/auth/refresh")
+                .set_json(json!({ "refresh_token": token }))
+                .to_request();
+            assert_eq!(
+                test::call_service(&app, request).await.status(),
+                StatusCode::OK
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn refresh_requires_bound_user_agent() {
+        let config = SessionConfig {
+            bind_user_agent: true,
+            ..Default::default()
+        };
+        let tracker = web::Data::new(SessionTracker::default());
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        let login = test::TestRequest::default()
+            .insert_header((header::USER_AGENT, "firefox"))
+            .to_http_request();
+        let claims = Claims::new("user", TEST_NOW - 10, &config).bind(&login, &config);
+        let token = track(&tracker, claims.to_refresh_token(&config));
+        let refresh = |user_agent: &str| {
+            test::TestRequest::post()
+                .uri("/api/auth/refresh")
+                .insert_header((header::USER_AGENT, user_agent.to_string()))
+                .set_json(json!({ "refresh_token": token }))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, refresh("curl")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: Value = serde_json::from_slice(&test::read_body(response).await).unwrap();
+        assert_eq!(body["error"], INVALID_SESSION);
+        let response = test::call_service(&app, refresh("firefox")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,130 @@
+use super::{SessionConfig, TokenType, prune::Prunable};
+use std::{collections::HashMap, sync::Mutex};
+
+/// tokens revoked before their natural expiry, keyed by `jti`, and per-user
+/// watermarks below which every token of that user is rejected.
+#[derive(Debug, Default)]
+pub struct RevocationStore {
+    revoked: Mutex<HashMap<String, u64>>,
+    watermarks: Mutex<HashMap<String, u64>>,
+}
+
+impl RevocationStore {
+    pub fn revoke(&self, jti: &str, exp: u64, now: u64, config: &SessionConfig) {
+        let mut revoked = self.revoked.lock().expect("revocation store poisoned");
+        // tokens past their expiry and leeway are rejected anyway, no need to
+        // remember them
+        let leeway = config.leeway.as_secs();
+        revoked.retain(|_, exp| *exp + leeway >= now);
+        revoked.insert(jti.to_string(), exp);
+    }
+
+    pub fn is_revoked(&self, jti: &str) -> bool {
+        self.revoked
+            .lock()
+            .expect("revocation store poisoned")
+            .contains_key(jti)
+    }
+
+    /// invalidate every token of `sub` issued before `now`. Timestamps have a
+    /// resolution of seconds, so a token issued in the same second survives,
+    /// or a login right after would be refused until the clock ticks over.
+    pub fn revoke_all(&self, sub: &str, now: u64) {
+        let mut watermarks = self.watermarks.lock().expect("revocation store poisoned");
+        let watermark = watermarks.entry(sub.to_string()).or_default();
+        *watermark = (*watermark).max(now);
+    }
+
+    pub fn is_revoked_before(&self, sub: &str, iat: u64) -> bool {
+        self.watermarks
+            .lock()
+            .expect("revocation store poisoned")
+            .get(sub)
+            .is_some_and(|watermark| iat < *watermark)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.revoked.lock().unwrap().len()
+    }
+}
+
+impl Prunable for RevocationStore {
+    fn prune(&self, now: u64, config: &SessionConfig) {
+        let leeway = config.leeway.as_secs();
+        self.revoked
+            .lock()
+            .expect("revocation store poisoned")
+            .retain(|_, exp| *exp + leeway >= now);
+        // once every token issued up to a watermark has expired, it rejects nothing
+        let lifetime = config
+            .max_token_lifetime(TokenType::Access)
+            .max(config.max_token_lifetime(TokenType::Refresh))
+            + leeway;
+        self.watermarks
+            .lock()
+            .expect("revocation store poisoned")
+            .retain(|_, watermark| *watermark + lifetime >= now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn revoke_marks_token() {
+        let config = SessionConfig::default();
+        let store = RevocationStore::default();
+        store.revoke("a", 200, 100, &config);
+
+        assert!(store.is_revoked("a"));
+        assert!(!store.is_revoked("b"));
+    }
+
+    #[test]
+    fn revoke_prunes_expired_entries() {
+        let config = SessionConfig {
+            leeway: Duration::ZERO,
+            ..Default::default()
+        };
+        let store = RevocationStore::default();
+        store.revoke("a", 200, 100, &config);
+        store.revoke("b", 400, 300, &config);
+
+        assert!(!store.is_revoked("a"));
+        assert!(store.is_revoked("b"));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn revoke_all_sets_watermark() {
+        let store = RevocationStore::default();
+        store.revoke_all("user", 200);
+        store.revoke_all("user", 100);
+
+        assert!(store.is_revoked_before("user", 150));
+        assert!(store.is_revoked_before("user", 199));
+        assert!(!store.is_revoked_before("user", 200));
+        assert!(!store.is_revoked_before("other", 150));
+    }
+
+    #[test]
+    fn revocation_outlives_expiry_by_leeway() {
+        let config = SessionConfig {
+            leeway: Duration::from_secs(30),
+            ..Default::default()
+        };
+        let store = RevocationStore::default();
+        store.revoke("a", 200, 100, &config);
+
+        // past `exp` the token still verifies within the leeway, so it has
+        // to stay revoked through sweeps until then
+        store.revoke("b", 400, 220, &config);
+        store.prune(230, &config);
+        assert!(store.is_revoked("a"));
+        store.prune(231, &config);
+        assert!(!store.is_revoked("a"));
+    }
+}
@@ -0,0 +1,107 @@
+use super::{AuthenticatedUser, SessionResponse, respond};
+use crate::error::ServerError;
+use actix_web::{HttpRequest, HttpResponse, get};
+
+/// describe the presented session without refreshing it.
+#[get("/session")]
+pub async fn handler(
+    http: HttpRequest,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ServerError> {
+    let body = SessionResponse::from_claims(user.token, &user.claims);
+    let body = if user.from_cookie {
+        body.without_token()
+    } else {
+        body
+    };
+    respond(HttpResponse::Ok(), &body, &http)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        Claims, SessionConfig, configure, issue_token, revocation::RevocationStore, test_clock,
+        test_token,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test, web,
+    };
+    use serde_json::Value;
+
+    async fn introspect(token: &str) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .configure(configure),
+        )
+        .await;
+        let request = test::TestRequest::get()
+            .uri("/api/auth/session")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn valid_session_is_described() {
+        let token = test_token("user");
+        let (status, body) = introspect(&token).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["token"], token);
+        assert_eq!(body["sub"], "user");
+        assert_eq!(body["provider"], "password");
+        assert!(body["expires_at"].as_u64().unwrap() > body["issued_at"].as_u64().unwrap());
+    }
+
+    #[actix_web::test]
+    async fn expired_session_is_unauthorized() {
+        let token = issue_token(&Claims::new("user", 100, &SessionConfig::default())).unwrap();
+        let (status, body) = introspect(&token).await;
+
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "invalid_token");
+    }
+
+    #[actix_web::test]
+    async fn session_expires_as_clock_advances() {
+        use crate::webpage::auth::{
+            TEST_NOW,
+            clock::{self, MockClock},
+        };
+        use std::sync::Arc;
+
+        let mock = Arc::new(MockClock::new(TEST_NOW));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(clock::data(mock.clone()))
+                .configure(configure),
+        )
+        .await;
+        let token = test_token("user");
+        let request = || {
+            test::TestRequest::get()
+                .uri("/api/auth/session")
+                .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+                .to_request()
+        };
+
+        let status = test::call_service(&app, request()).await.status();
+        assert_eq!(status, StatusCode::OK);
+
+        let config = SessionConfig::default();
+        mock.advance(config.ttl.as_secs() + config.leeway.as_secs() + 1);
+        let status = test::call_service(&app, request()).await.status();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,109 @@
+use super::{
+    AuthenticatedUser,
+    clock::Clock,
+    tracker::{SessionInfo, SessionTracker},
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct ActiveSession {
+    id: String,
+    current: bool,
+    #[serde(flatten)]
+    info: SessionInfo,
+}
+
+/// the active sessions of the authenticated user, e.g. other devices.
+#[get("/sessions")]
+pub async fn handler(
+    user: AuthenticatedUser,
+    tracker: web::Data<SessionTracker>,
+    clock: web::Data<dyn Clock>,
+) -> impl Responder {
+    let sessions: Vec<_> = tracker
+        .list(&user.claims.sub, clock.now_secs())
+        .into_iter()
+        .map(|(id, info)| ActiveSession {
+            current: id == user.claims.sid,
+            id,
+            info,
+        })
+        .collect();
+    HttpResponse::Ok().json(sessions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        Claims, SessionConfig, TEST_NOW, configure, issue_token, revocation::RevocationStore,
+        test_clock,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+    use serde_json::Value;
+
+    fn login(tracker: &SessionTracker, sub: &str, user_agent: &str) -> (Claims, String) {
+        let claims = Claims::new(sub, TEST_NOW - 1, &SessionConfig::default());
+        tracker.start(
+            &claims.sid,
+            SessionInfo {
+                sub: sub.to_string(),
+                issued_at: claims.iat,
+                last_active: claims.iat,
+                ip: String::from("127.0.0.1"),
+                user_agent: user_agent.to_string(),
+                jti: claims.jti.clone(),
+                expires_at: claims.exp,
+            },
+            claims.iat,
+        );
+        let token = issue_token(&claims).unwrap();
+        (claims, token)
+    }
+
+    #[actix_web::test]
+    async fn user_only_sees_own_sessions() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let (current, token) = login(&tracker, "user", "laptop");
+        login(&tracker, "user", "phone");
+        login(&tracker, "other", "desktop");
+
+        let app = init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        let request = TestRequest::get()
+            .uri("/api/auth/sessions")
+            .insert_header((header::AUTHORIZATION, format!("Bearer {}", token)))
+            .to_request();
+        let response = call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body: Value = serde_json::from_slice(&read_body(response).await).unwrap();
+        let sessions = body.as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(
+            sessions
+                .iter()
+                .all(|session| session["user_agent"] != "desktop")
+        );
+
+        let marked: Vec<_> = sessions
+            .iter()
+            .filter(|session| session["current"] == true)
+            .collect();
+        assert_eq!(marked.len(), 1);
+        assert_eq!(marked[0]["id"], current.sid.as_str());
+        assert_eq!(marked[0]["user_agent"], "laptop");
+    }
+}
@@ -0,0 +1,399 @@
+use super::SessionConfig;
+#[cfg(feature = "login-log-file")]
+use super::login_log::{self, LoginLogConfig};
+use crate::{
+    database::{self, login_history::LoginEvent},
+    error::ServerError,
+    webpage::request_id,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "login-log-file")]
+use std::path::PathBuf;
+use std::{net::IpAddr, time::Duration};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum AuthEventKind {
+    Login,
+    Logout,
+    SuspiciousLogin,
+    LoginFailed,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct AuthEvent {
+    event: AuthEventKind,
+    sub: String,
+    ip: String,
+    provider: String,
+    timestamp: DateTime<Utc>,
+    // lines from before deployments were stamped
+    #[serde(default)]
+    deployment: String,
+    // the request the event happened in, when known
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    // why a login was refused, never the credential itself
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+fn auth_event(
+    kind: AuthEventKind,
+    sub: &str,
+    ip: &str,
+    timestamp: u64,
+    deployment: &str,
+) -> AuthEvent {
+    AuthEvent {
+        event: kind,
+        sub: sub.to_string(),
+        ip: ip.to_string(),
+        provider: String::from("password"),
+        timestamp: DateTime::from_timestamp(timestamp as i64, 0).expect("Can't get time"),
+        deployment: deployment.to_string(),
+        request_id: request_id::current(),
+        reason: None,
+    }
+}
+
+/// an auth event as a single json line.
+fn auth_event_line(
+    kind: AuthEventKind,
+    sub: &str,
+    ip: &str,
+    timestamp: u64,
+    deployment: &str,
+) -> Result<String, ServerError> {
+    let event = auth_event(kind, sub, ip, timestamp, deployment);
+    Ok(serde_json::to_string(&event)?)
+}
+
+/// serialize an auth event as a single json line and log it.
+pub(super) fn record_auth_event(
+    kind: AuthEventKind,
+    sub: &str,
+    ip: &str,
+    timestamp: u64,
+    deployment: &str,
+) -> Result<String, ServerError> {
+    let line = auth_event_line(kind, sub, ip, timestamp, deployment)?;
+    log::info!("{}", line);
+    Ok(line)
+}
+
+/// log a refused login of `sub` with the reason it was refused, for the
+/// audit trail successful logins already leave.
+pub(super) fn record_login_failure(
+    sub: &str,
+    ip: &str,
+    reason: &str,
+    timestamp: u64,
+    deployment: &str,
+) -> Result<String, ServerError> {
+    let event = AuthEvent {
+        reason: Some(reason.to_string()),
+        ..auth_event(AuthEventKind::LoginFailed, sub, ip, timestamp, deployment)
+    };
+    let line = serde_json::to_string(&event)?;
+    log::warn!("{}", line);
+    Ok(line)
+}
+
+/// a destination for login events.
+pub trait LoginSink: Send + Sync {
+    fn record(&self, event: &LoginEvent) -> Result<(), ServerError>;
+}
+
+/// every registered sink, each receiving every event.
+#[derive(Default)]
+pub struct LoginSinks(Vec<Box<dyn LoginSink>>);
+
+impl LoginSinks {
+    pub fn with(mut self, sink: impl LoginSink + 'static) -> Self {
+        self.0.push(Box::new(sink));
+        self
+    }
+
+    /// the database, the log, and the login log file when enabled.
+    pub fn standard(config: &SessionConfig) -> Self {
+        let deployment = config.deployment_id.clone();
+        let sinks = Self::default()
+            .with(DatabaseSink {
+                window: config.suspicious_login_window,
+                deployment: deployment.clone(),
+            })
+            .with(LogSink {
+                deployment: deployment.clone(),
+            });
+        #[cfg(feature = "login-log-file")]
+        let sinks = sinks.with(FileSink {
+            path: PathBuf::from(login_log::LOGIN_LOG_PATH),
+            config: crate::config::CONFIG.login_log.clone(),
+            deployment,
+        });
+        sinks
+    }
+}
+
+impl LoginSink for LoginSinks {
+    /// a failing sink does not keep the event from the others, the first
+    /// failure is returned once all have run.
+    fn record(&self, event: &LoginEvent) -> Result<(), ServerError> {
+        let mut result = Ok(());
+        for sink in &self.0 {
+            if let Err(err) = sink.record(event) {
+                if result.is_ok() {
+                    result = Err(err);
+                } else {
+                    log::error!("fail to record login event: {:?}", err);
+                }
+            }
+        }
+        result
+    }
+}
+
+/// whether two addresses are on different networks: a different /16 for
+/// ipv4, a different /48 for ipv6, or a different address family.
+fn distant(a: IpAddr, b: IpAddr) -> bool {
+    match (a, b) {
+        (IpAddr::V4(a), IpAddr::V4(b)) => a.octets()[..2] != b.octets()[..2],
+        (IpAddr::V6(a), IpAddr::V6(b)) => a.segments()[..3] != b.segments()[..3],
+        _ => true,
+    }
+}
+
+/// a login shortly after the previous one of the same user, but from a
+/// distant network. Unknown addresses are never suspicious.
+fn is_suspicious(previous: &LoginEvent, current: &LoginEvent, window: Duration) -> bool {
+    let (Ok(before), Ok(now)) = (previous.ip.parse::<IpAddr>(), current.ip.parse::<IpAddr>())
+    else {
+        return false;
+    };
+    let elapsed = (current.created_at - previous.created_at).abs();
+    elapsed.to_std().is_ok_and(|elapsed| elapsed <= window) && distant(before, now)
+}
+
+/// stores the event in login history, flagging suspicious logins on the way.
+pub struct DatabaseSink {
+    pub window: Duration,
+    pub deployment: String,
+}
+
+impl LoginSink for DatabaseSink {
+    fn record(&self, event: &LoginEvent) -> Result<(), ServerError> {
+        let mut event = event.clone();
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+        let previous = LoginEvent::last(&event.sub, &transaction)?;
+        event.insert(&transaction)?;
+        transaction.commit()?;
+
+        if previous.is_some_and(|previous| is_suspicious(&previous, &event, self.window)) {
+            let timestamp = event.created_at.timestamp() as u64;
+            record_auth_event(
+                AuthEventKind::SuspiciousLogin,
+                &event.sub,
+                &event.ip,
+                timestamp,
+                &self.deployment,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// writes the event to the log as a json line.
+pub struct LogSink {
+    pub deployment: String,
+}
+
+impl LoginSink for LogSink {
+    fn record(&self, event: &LoginEvent) -> Result<(), ServerError> {
+        let timestamp = event.created_at.timestamp() as u64;
+        record_auth_event(
+            AuthEventKind::Login,
+            &event.sub,
+            &event.ip,
+            timestamp,
+            &self.deployment,
+        )?;
+        Ok(())
+    }
+}
+
+/// appends the event as a json line to a rolling log file.
+#[cfg(feature = "login-log-file")]
+pub struct FileSink {
+    pub path: PathBuf,
+    pub config: LoginLogConfig,
+    pub deployment: String,
+}
+
+#[cfg(feature = "login-log-file")]
+impl LoginSink for FileSink {
+    fn record(&self, event: &LoginEvent) -> Result<(), ServerError> {
+        let timestamp = event.created_at.timestamp() as u64;
+        let line = auth_event_line(
+            AuthEventKind::Login,
+            &event.sub,
+            &event.ip,
+            timestamp,
+            &self.deployment,
+        )?;
+        login_log::append(&self.path, &line, &self.config)?;
+        Ok(())
+    }
+}
+
+/// drops every event.
+pub struct NoopSink;
+
+impl LoginSink for NoopSink {
+    fn record(&self, _: &LoginEvent) -> Result<(), ServerError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn login(ip: &str, at: i64) -> LoginEvent {
+        LoginEvent {
+            id: 0,
+            sub: String::from("user"),
+            provider: String::from("password"),
+            ip: ip.to_string(),
+            user_agent: String::from("test"),
+            created_at: DateTime::from_timestamp(at, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn auth_event_round_trips_through_json() {
+        let line = record_auth_event(
+            AuthEventKind::Logout,
+            "us\"er\n",
+            "127.0.0.1",
+            100,
+            "staging",
+        )
+        .unwrap();
+        assert_eq!(line.lines().count(), 1);
+
+        let event: AuthEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(
+            event,
+            AuthEvent {
+                event: AuthEventKind::Logout,
+                sub: String::from("us\"er\n"),
+                ip: String::from("127.0.0.1"),
+                provider: String::from("password"),
+                timestamp: DateTime::from_timestamp(100, 0).unwrap(),
+                deployment: String::from("staging"),
+                request_id: None,
+                reason: None,
+            }
+        );
+    }
+
+    #[test]
+    fn login_failure_carries_reason() {
+        let line = record_login_failure("user", "127.0.0.1", "invalid_credential", 100, "default")
+            .unwrap();
+        let event: AuthEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(event.event, AuthEventKind::LoginFailed);
+        assert_eq!(event.sub, "user");
+        assert_eq!(event.reason.as_deref(), Some("invalid_credential"));
+
+        let line = record_auth_event(AuthEventKind::Login, "user", "127.0.0.1", 100, "default");
+        assert!(!line.unwrap().contains("reason"));
+    }
+
+    #[test]
+    fn auth_event_carries_request_id() {
+        let line = request_id::scope(Some(String::from("abc-123")), || {
+            auth_event_line(AuthEventKind::Login, "user", "127.0.0.1", 100, "default")
+        })
+        .unwrap();
+        let event: AuthEvent = serde_json::from_str(&line).unwrap();
+        assert_eq!(event.request_id.as_deref(), Some("abc-123"));
+
+        let line = auth_event_line(AuthEventKind::Login, "user", "127.0.0.1", 100, "default");
+        assert!(!line.unwrap().contains("request_id"));
+    }
+
+    #[test]
+    fn rapid_logins_from_distant_networks_are_suspicious() {
+        let window = Duration::from_secs(3600);
+
+        let first = login("203.0.113.7", 1000);
+        assert!(is_suspicious(&first, &login("198.51.100.1", 1060), window));
+        assert!(is_suspicious(&first, &login("2001:db8::1", 1060), window));
+        assert!(!is_suspicious(&first, &login("203.0.113.7", 1060), window));
+        assert!(!is_suspicious(&first, &login("203.0.1.1", 1060), window));
+        assert!(!is_suspicious(
+            &first,
+            &login("198.51.100.1", 1000 + 7200),
+            window
+        ));
+        assert!(!is_suspicious(&first, &login("unknown", 1060), window));
+    }
+
+    struct RecordingSink(Arc<Mutex<Vec<String>>>);
+
+    impl LoginSink for RecordingSink {
+        fn record(&self, event: &LoginEvent) -> Result<(), ServerError> {
+            self.0.lock().unwrap().push(event.ip.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    impl LoginSink for FailingSink {
+        fn record(&self, _: &LoginEvent) -> Result<(), ServerError> {
+            Err(ServerError::Internal(String::from("sink down")))
+        }
+    }
+
+    #[test]
+    fn every_sink_receives_the_event() {
+        let first = Arc::new(Mutex::new(Vec::new()));
+        let second = Arc::new(Mutex::new(Vec::new()));
+        let sinks = LoginSinks::default()
+            .with(RecordingSink(first.clone()))
+            .with(FailingSink)
+            .with(NoopSink)
+            .with(RecordingSink(second.clone()));
+
+        assert!(sinks.record(&login("127.0.0.1", 100)).is_err());
+        assert_eq!(*first.lock().unwrap(), ["127.0.0.1"]);
+        assert_eq!(*second.lock().unwrap(), ["127.0.0.1"]);
+    }
+
+    #[cfg(feature = "login-log-file")]
+    #[test]
+    fn file_sink_appends_json_line() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&dir).unwrap();
+        let sink = FileSink {
+            path: dir.join("login_history.log"),
+            config: LoginLogConfig::default(),
+            deployment: String::from("staging"),
+        };
+
+        sink.record(&login("127.0.0.1", 100)).unwrap();
+        let contents = std::fs::read_to_string(&sink.path).unwrap();
+        let event: AuthEvent = serde_json::from_str(contents.trim_end()).unwrap();
+        assert_eq!(event.event, AuthEventKind::Login);
+        assert_eq!(event.ip, "127.0.0.1");
+        assert_eq!(event.deployment, "staging");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
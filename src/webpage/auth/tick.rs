@@ -1,27 +1,406 @@
-use super::{Claims, PRIVATE_KEY, verify};
-use crate::error::ServerError;
-use actix_web::{HttpResponse, Responder, post, web};
-use jwt::SignWithKey;
+use super::{
+    SessionConfig, SessionResponse, TokenType, VerifyError,
+    clock::Clock,
+    cookie_token, issue_token,
+    origin::SameOrigin,
+    respond,
+    revocation::RevocationStore,
+    session_cookie_for,
+    tracker::{Rotation, SessionTracker},
+    verify_detailed,
+};
+use crate::{error::ServerError, webpage::metrics::METRICS};
+use actix_web::{HttpRequest, HttpResponse, error::JsonPayloadError, post, web};
 use serde::Deserialize;
-use std::time::{SystemTime, UNIX_EPOCH};
+use serde_json::json;
+
+/// the session ran its course, the user has to log in again
+pub(super) const SESSION_EXPIRED: &str = "session_expired";
+/// the token was never good, or was taken out of circulation
+pub(super) const INVALID_SESSION: &str = "invalid_session";
+
+pub(super) fn forbidden(reason: &str) -> HttpResponse {
+    METRICS.tick_rejected();
+    HttpResponse::Forbidden().json(json!({ "error": reason }))
+}
 
 #[derive(Debug, Deserialize)]
 struct Request {
-    token: String,
+    #[serde(default)]
+    token: Option<String>,
 }
 
-#[post("/api/auth/tick")]
-pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
-    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+#[post("/tick")]
+pub async fn handler(
+    http: HttpRequest,
+    _: SameOrigin,
+    request: Result<web::Json<Request>, actix_web::Error>,
+    config: web::Data<SessionConfig>,
+    revocations: web::Data<RevocationStore>,
+    clock: web::Data<dyn Clock>,
+    tracker: web::Data<SessionTracker>,
+) -> Result<HttpResponse, ServerError> {
+    let now = clock.now_secs();
+    let body = match request {
+        Ok(request) => request.into_inner().token,
+        // no json body, the token can only be in the cookie
+        Err(err) if matches!(err.as_error(), Some(JsonPayloadError::ContentType)) => None,
+        Err(err) => return Ok(err.error_response()),
+    };
+    // the body wins over the cookie, for clients not yet migrated
+    let from_cookie = body.is_none();
+    let token = body.or_else(|| cookie_token(&http)).unwrap_or_default();
 
-    if verify(&request.token, now) {
-        let claims = Claims {
-            iat: now,
-            exp: now + 3600,
-        };
-        let token = claims.sign_with_key(&*PRIVATE_KEY)?;
-        Ok(HttpResponse::Ok().body(token))
+    // the reason lets the frontend tell a lapsed session from a rejected one
+    let presented = match verify_detailed(&token, TokenType::Access, now, &config, &revocations) {
+        Ok(presented) => presented,
+        Err(VerifyError::Expired) => return Ok(forbidden(SESSION_EXPIRED)),
+        Err(_) => return Ok(forbidden(INVALID_SESSION)),
+    };
+    if !presented.bound_to(&http, &config) {
+        log::warn!(
+            "token of session {} presented by another user agent",
+            presented.sid
+        );
+        return Ok(forbidden(INVALID_SESSION));
+    }
+    if !presented.refreshable(now, &config) {
+        return Ok(forbidden(SESSION_EXPIRED));
+    }
+
+    let claims = presented.refresh(now, &config);
+    let token = issue_token(&claims)?;
+    // every tick hands out a new jti, so a token ticked before is a copy
+    if let Rotation::Replayed {
+        current,
+        expires_at,
+    } = tracker.rotate(&claims.sid, &presented.jti, &claims.jti, now, claims.exp)
+    {
+        log::warn!(
+            "replayed token for session {} of {}",
+            claims.sid,
+            claims.sub
+        );
+        revocations.revoke(&presented.jti, presented.exp, now, &config);
+        revocations.revoke(&current, expires_at, now, &config);
+        tracker.end(&claims.sid);
+        return Ok(forbidden(INVALID_SESSION));
+    }
+    revocations.revoke(&presented.jti, presented.exp, now, &config);
+    METRICS.tick_refreshed();
+
+    // a cookie session is carried on in the cookie, its token never reaches
+    // scripts and the spent one is replaced whatever `set_cookie` says
+    let mut response = HttpResponse::Ok();
+    if config.set_cookie || from_cookie {
+        response.cookie(session_cookie_for(&token, &claims, &config));
+    }
+    let body = SessionResponse::from_claims(token, &claims);
+    let body = if from_cookie {
+        body.without_token()
     } else {
-        Ok(HttpResponse::Forbidden().finish())
+        body
+    };
+    respond(response, &body, &http)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webpage::auth::{
+        Claims, JSON_LIMIT, TEST_NOW, configure, session_cookie, test_clock, tracker::SessionInfo,
+    };
+    use actix_web::{
+        App,
+        http::{StatusCode, header},
+        test,
+    };
+    use serde_json::{Value, json};
+    use std::time::Duration;
+
+    async fn tick(token: String) -> (StatusCode, Value) {
+        tick_with(SessionConfig::default(), token).await
+    }
+
+    async fn tick_with(config: SessionConfig, token: String) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let request = test::TestRequest::post()
+            .uri("/api/auth/tick")
+            .set_json(json!({ "token": token }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn tick_tracked(
+        tracker: &web::Data<SessionTracker>,
+        revocations: &web::Data<RevocationStore>,
+        token: &str,
+    ) -> (StatusCode, Value) {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(revocations.clone())
+                .app_data(test_clock())
+                .app_data(tracker.clone())
+                .configure(configure),
+        )
+        .await;
+        let request = test::TestRequest::post()
+            .uri("/api/auth/tick")
+            .set_json(json!({ "token": token }))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        let status = response.status();
+        let body = test::read_body(response).await;
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    fn tracked(tracker: &SessionTracker) -> Claims {
+        let claims = Claims::new("user", TEST_NOW - 10, &SessionConfig::default());
+        tracker.start(
+            &claims.sid,
+            SessionInfo {
+                sub: claims.sub.clone(),
+                issued_at: claims.iat,
+                last_active: claims.iat,
+                ip: String::from("127.0.0.1"),
+                user_agent: String::from("test"),
+                jti: claims.jti.clone(),
+                expires_at: claims.exp,
+            },
+            claims.iat,
+        );
+        claims
+    }
+
+    #[actix_web::test]
+    async fn tick_rotates_jti() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let revocations = web::Data::new(RevocationStore::default());
+        let claims = tracked(&tracker);
+        let token = issue_token(&claims).unwrap();
+
+        let (status, body) = tick_tracked(&tracker, &revocations, &token).await;
+        assert_eq!(status, StatusCode::OK);
+        let rotated = body["token"].as_str().unwrap().to_string();
+        assert!(revocations.is_revoked(&claims.jti));
+
+        // the old token is spent, the new one ticks on
+        let (status, _) = tick_tracked(&tracker, &revocations, &token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = tick_tracked(&tracker, &revocations, &rotated).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn replayed_token_ends_session() {
+        let tracker = web::Data::new(SessionTracker::default());
+        let revocations = web::Data::new(RevocationStore::default());
+        let claims = tracked(&tracker);
+        // the session has moved on to another token, e.g. through /refresh
+        tracker.touch(&claims.sid, "current", TEST_NOW - 5, claims.exp);
+        let token = issue_token(&claims).unwrap();
+
+        let (status, body) = tick_tracked(&tracker, &revocations, &token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], INVALID_SESSION);
+        assert!(revocations.is_revoked(&claims.jti));
+        assert!(revocations.is_revoked("current"));
+        assert!(tracker.list("user", TEST_NOW).is_empty());
+    }
+
+    fn token_with_auth_time(auth_time: u64) -> String {
+        let now = TEST_NOW;
+        issue_token(&Claims {
+            exp: now + 600,
+            auth_time: Some(auth_time),
+            ..Claims::new("user", now - 10, &SessionConfig::default())
+        })
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn refresh_allowed_under_lifetime_cap() {
+        let cap = SessionConfig::default().max_lifetime.as_secs();
+        let now = TEST_NOW;
+
+        let token = token_with_auth_time(now - cap + 60);
+        let (status, body) = tick(token).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["token"].is_string());
+
+        let issued_at = body["issued_at"].as_u64().unwrap();
+        let expires_at = body["expires_at"].as_u64().unwrap();
+        assert!(issued_at >= now);
+        assert_eq!(
+            expires_at - issued_at,
+            SessionConfig::default().ttl.as_secs()
+        );
+    }
+
+    #[actix_web::test]
+    async fn refresh_rejected_over_lifetime_cap() {
+        let cap = SessionConfig::default().max_lifetime.as_secs();
+        let now = TEST_NOW;
+
+        let token = token_with_auth_time(now - cap - 60);
+        let (status, body) = tick(token).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], SESSION_EXPIRED);
+    }
+
+    #[actix_web::test]
+    async fn expired_and_invalid_tokens_are_told_apart() {
+        let expired = issue_token(&Claims::new(
+            "user",
+            TEST_NOW - 7200,
+            &SessionConfig::default(),
+        ))
+        .unwrap();
+        let (status, body) = tick(expired).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, json!({ "error": "session_expired" }));
+
+        let (status, body) = tick(String::from("invalid")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, json!({ "error": "invalid_session" }));
+    }
+
+    #[actix_web::test]
+    async fn tick_requires_bound_user_agent() {
+        let config = SessionConfig {
+            bind_user_agent: true,
+            ..SessionConfig::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let login = test::TestRequest::default()
+            .insert_header((header::USER_AGENT, "firefox"))
+            .to_http_request();
+        let token = issue_token(&Claims::new("user", TEST_NOW - 10, &config).bind(&login, &config))
+            .unwrap();
+        let tick = |user_agent: &str| {
+            test::TestRequest::post()
+                .uri("/api/auth/tick")
+                .insert_header((header::USER_AGENT, user_agent.to_string()))
+                .set_json(json!({ "token": token }))
+                .to_request()
+        };
+
+        let response = test::call_service(&app, tick("curl")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = test::call_service(&app, tick("firefox")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn idle_session_is_rejected() {
+        let config = SessionConfig {
+            idle_timeout: Some(Duration::from_secs(600)),
+            ..Default::default()
+        };
+        let now = TEST_NOW;
+        let token = |last_active: u64| {
+            issue_token(&Claims {
+                exp: now + 600,
+                last_active: Some(last_active),
+                ..Claims::new("user", now - 10, &config)
+            })
+            .unwrap()
+        };
+
+        let (status, _) = tick_with(config.clone(), token(now - 660)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        // ticking regularly keeps the session alive
+        let (status, body) = tick_with(config.clone(), token(now - 540)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = tick_with(config, body["token"].as_str().unwrap().to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn cookie_only_request_is_refreshed_into_cookie() {
+        let config = SessionConfig {
+            set_cookie: true,
+            cookie_domain: Some(String::from("mercuryland.dev")),
+            ..Default::default()
+        };
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(config.clone()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let now = TEST_NOW;
+        let token = token_with_auth_time(now - 10);
+        let request = test::TestRequest::post()
+            .uri("/api/auth/tick")
+            .cookie(session_cookie(&token, &config))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let cookie = response
+            .response()
+            .cookies()
+            .find(|cookie| cookie.name() == "session")
+            .unwrap();
+        assert_ne!(cookie.value(), token);
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.domain(), Some("mercuryland.dev"));
+        assert_eq!(cookie.path(), Some("/api"));
+    }
+
+    #[actix_web::test]
+    async fn oversized_body_is_rejected() {
+        let padding = r#"{"token":""}"#.len();
+        let body = |len: usize| format!(r#"{{"token":"{}"}}"#, "a".repeat(len - padding));
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(SessionConfig::default()))
+                .app_data(web::Data::new(RevocationStore::default()))
+                .app_data(test_clock())
+                .app_data(web::Data::new(SessionTracker::default()))
+                .configure(configure),
+        )
+        .await;
+        let request = |len| {
+            test::TestRequest::post()
+                .uri("/api/auth/tick")
+                .insert_header(("content-type", "application/json"))
+                .set_payload(body(len))
+                .to_request()
+        };
+
+        let status = test::call_service(&app, request(JSON_LIMIT + 1))
+            .await
+            .status();
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        // parsed, and rejected only as an invalid token
+        let status = test::call_service(&app, request(JSON_LIMIT)).await.status();
+        assert_eq!(status, StatusCode::FORBIDDEN);
     }
 }
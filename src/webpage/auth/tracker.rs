@@ -0,0 +1,203 @@
+use super::{SessionConfig, prune::Prunable};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionInfo {
+    #[serde(skip)]
+    pub sub: String,
+    pub issued_at: u64,
+    pub last_active: u64,
+    pub ip: String,
+    pub user_agent: String,
+    /// `jti` of the access token most recently handed out for the session
+    #[serde(skip)]
+    pub jti: String,
+    /// when the last token of the session expires
+    #[serde(skip)]
+    pub expires_at: u64,
+}
+
+/// outcome of handing a session on to a new access token.
+#[derive(Debug, PartialEq)]
+pub enum Rotation {
+    Rotated,
+    /// the presented token was already rotated away from; `current` is the
+    /// token handed out since, valid until `expires_at`
+    Replayed {
+        current: String,
+        expires_at: u64,
+    },
+}
+
+/// sessions started at login, keyed by `sid`, until logout or expiry.
+#[derive(Debug, Default)]
+pub struct SessionTracker {
+    sessions: Mutex<HashMap<String, SessionInfo>>,
+}
+
+impl SessionTracker {
+    pub fn start(&self, sid: &str, info: SessionInfo, now: u64) {
+        let mut sessions = self.sessions.lock().expect("session tracker poisoned");
+        sessions.retain(|_, session| session.expires_at >= now);
+        sessions.insert(sid.to_string(), info);
+    }
+
+    /// record activity on `sid`, which now continues with the token `jti`
+    /// valid until `exp`.
+    pub fn touch(&self, sid: &str, jti: &str, now: u64, exp: u64) {
+        let mut sessions = self.sessions.lock().expect("session tracker poisoned");
+        if let Some(session) = sessions.get_mut(sid) {
+            session.last_active = now;
+            session.jti = jti.to_string();
+            session.expires_at = session.expires_at.max(exp);
+        }
+    }
+
+    /// like `touch`, but only if `presented` is still the current token of
+    /// `sid`. Sessions not tracked, e.g. from before a restart, are not
+    /// checked.
+    pub fn rotate(&self, sid: &str, presented: &str, next: &str, now: u64, exp: u64) -> Rotation {
+        let mut sessions = self.sessions.lock().expect("session tracker poisoned");
+        let Some(session) = sessions.get_mut(sid) else {
+            return Rotation::Rotated;
+        };
+        if session.jti != presented {
+            return Rotation::Replayed {
+                current: session.jti.clone(),
+                expires_at: session.expires_at,
+            };
+        }
+        session.last_active = now;
+        session.jti = next.to_string();
+        session.expires_at = session.expires_at.max(exp);
+        Rotation::Rotated
+    }
+
+    pub fn end(&self, sid: &str) {
+        self.sessions
+            .lock()
+            .expect("session tracker poisoned")
+            .remove(sid);
+    }
+
+    /// end every session of `sub`, returning how many there were.
+    pub fn end_all(&self, sub: &str) -> usize {
+        let mut sessions = self.sessions.lock().expect("session tracker poisoned");
+        let before = sessions.len();
+        sessions.retain(|_, session| session.sub != sub);
+        before - sessions.len()
+    }
+
+    /// end every session of everyone, returning how many there were.
+    pub fn clear(&self) -> usize {
+        let mut sessions = self.sessions.lock().expect("session tracker poisoned");
+        let count = sessions.len();
+        sessions.clear();
+        count
+    }
+
+    /// when `sid` was last active, if it is tracked.
+    pub fn last_active(&self, sid: &str) -> Option<u64> {
+        self.sessions
+            .lock()
+            .expect("session tracker poisoned")
+            .get(sid)
+            .map(|session| session.last_active)
+    }
+
+    /// the unexpired sessions of `sub`, most recently active first.
+    pub fn list(&self, sub: &str, now: u64) -> Vec<(String, SessionInfo)> {
+        let sessions = self.sessions.lock().expect("session tracker poisoned");
+        let mut list: Vec<_> = sessions
+            .iter()
+            .filter(|(_, session)| session.sub == sub && session.expires_at >= now)
+            .map(|(sid, session)| (sid.clone(), session.clone()))
+            .collect();
+        list.sort_by_key(|(_, session)| std::cmp::Reverse(session.last_active));
+        list
+    }
+}
+
+impl Prunable for SessionTracker {
+    fn prune(&self, now: u64, _: &SessionConfig) {
+        self.sessions
+            .lock()
+            .expect("session tracker poisoned")
+            .retain(|_, session| session.expires_at >= now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(sub: &str, now: u64) -> SessionInfo {
+        SessionInfo {
+            sub: sub.to_string(),
+            issued_at: now,
+            last_active: now,
+            ip: String::from("127.0.0.1"),
+            user_agent: String::from("test"),
+            jti: String::from("first"),
+            expires_at: now + 100,
+        }
+    }
+
+    #[test]
+    fn sessions_are_listed_per_user() {
+        let tracker = SessionTracker::default();
+        tracker.start("a", info("user", 100), 100);
+        tracker.start("b", info("user", 110), 110);
+        tracker.start("c", info("other", 110), 110);
+        tracker.touch("a", "second", 120, 300);
+
+        let list = tracker.list("user", 150);
+        let sids: Vec<_> = list.iter().map(|(sid, _)| sid.as_str()).collect();
+        assert_eq!(sids, ["a", "b"]);
+        assert_eq!(list[0].1.last_active, 120);
+
+        // `b` expires at 210, `a` was extended to 300
+        assert_eq!(tracker.list("user", 250).len(), 1);
+    }
+
+    #[test]
+    fn ended_sessions_are_forgotten() {
+        let tracker = SessionTracker::default();
+        tracker.start("a", info("user", 100), 100);
+        tracker.start("b", info("user", 100), 100);
+        tracker.start("c", info("other", 100), 100);
+
+        tracker.end("a");
+        assert_eq!(tracker.list("user", 100).len(), 1);
+        assert_eq!(tracker.end_all("user"), 1);
+        assert!(tracker.list("user", 100).is_empty());
+        assert_eq!(tracker.list("other", 100).len(), 1);
+    }
+
+    #[test]
+    fn rotation_detects_replay() {
+        let tracker = SessionTracker::default();
+        tracker.start("a", info("user", 100), 100);
+
+        assert_eq!(
+            tracker.rotate("a", "first", "second", 110, 300),
+            Rotation::Rotated
+        );
+        assert_eq!(
+            tracker.rotate("a", "first", "third", 120, 300),
+            Rotation::Replayed {
+                current: String::from("second"),
+                expires_at: 300,
+            }
+        );
+        assert_eq!(
+            tracker.rotate("a", "second", "third", 120, 300),
+            Rotation::Rotated
+        );
+        assert_eq!(
+            tracker.rotate("b", "any", "next", 120, 300),
+            Rotation::Rotated
+        );
+    }
+}
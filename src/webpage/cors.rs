@@ -0,0 +1,119 @@
+use crate::error::ServerError;
+use actix_cors::Cors;
+use actix_web::http::header;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// origins allowed to call the api, `"*"` allows any origin
+    pub allowed_origins: Vec<String>,
+    pub supports_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![
+                String::from("http://localhost:5173"),
+                String::from("https://mercuryland.pp.ua"),
+                String::from("https://www.mercuryland.pp.ua"),
+            ],
+            supports_credentials: false,
+        }
+    }
+}
+
+impl CorsConfig {
+    fn allows_any_origin(&self) -> bool {
+        self.allowed_origins.iter().any(|origin| origin == "*")
+    }
+
+    /// browsers refuse credentialed requests to a wildcard origin, so refuse
+    /// such a config at startup instead.
+    pub fn validate(&self) -> Result<(), ServerError> {
+        if self.supports_credentials && self.allows_any_origin() {
+            return Err(ServerError::Internal(String::from(
+                "cors: wildcard origin cannot be used with credentials",
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn cors(config: &CorsConfig) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(vec!["GET", "POST"])
+        .allowed_headers(vec![header::AUTHORIZATION, header::CONTENT_TYPE]);
+
+    if config.allows_any_origin() {
+        cors = cors.allow_any_origin();
+    } else {
+        for origin in &config.allowed_origins {
+            cors = cors.allowed_origin(origin);
+        }
+    }
+
+    if config.supports_credentials {
+        cors = cors.supports_credentials();
+    }
+    cors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App, HttpResponse,
+        http::{Method, StatusCode},
+        test::{TestRequest, call_service, init_service},
+        web,
+    };
+
+    async fn preflight(origin: &str) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .wrap(cors(&CorsConfig::default()))
+                .route("/api/auth/tick", web::post().to(HttpResponse::Ok)),
+        )
+        .await;
+        let request = TestRequest::default()
+            .method(Method::OPTIONS)
+            .uri("/api/auth/tick")
+            .insert_header((header::ORIGIN, origin))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "POST"))
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+            .to_request();
+        call_service(&app, request).await.status()
+    }
+
+    #[actix_web::test]
+    async fn preflight_from_allowed_origin_succeeds() {
+        assert_eq!(preflight("https://mercuryland.pp.ua").await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn preflight_from_other_origin_is_blocked() {
+        assert_eq!(
+            preflight("https://evil.example").await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[test]
+    fn wildcard_with_credentials_is_rejected() {
+        let config = CorsConfig {
+            allowed_origins: vec![String::from("*")],
+            supports_credentials: true,
+        };
+        assert!(config.validate().is_err());
+        assert!(
+            CorsConfig {
+                supports_credentials: false,
+                ..config
+            }
+            .validate()
+            .is_ok()
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use actix_web::{HttpResponse, Responder, get, http::header::ContentType};
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+pub static METRICS: LazyLock<AuthMetrics> = LazyLock::new(AuthMetrics::default);
+
+/// counters for authentication outcomes.
+#[derive(Debug, Default)]
+pub struct AuthMetrics {
+    login_success: AtomicU64,
+    login_failure: Mutex<BTreeMap<&'static str, u64>>,
+    tick_refresh: AtomicU64,
+    tick_rejected: AtomicU64,
+    logout: AtomicU64,
+}
+
+impl AuthMetrics {
+    pub fn login_succeeded(&self) {
+        self.login_success.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn login_failed(&self, reason: &'static str) {
+        let mut failures = self.login_failure.lock().expect("metrics poisoned");
+        *failures.entry(reason).or_default() += 1;
+    }
+
+    pub fn tick_refreshed(&self) {
+        self.tick_refresh.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn tick_rejected(&self) {
+        self.tick_rejected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn logged_out(&self) {
+        self.logout.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// render in the prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut counter = |name: &str, value: &AtomicU64| {
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        };
+        counter("auth_login_success_total", &self.login_success);
+        counter("auth_tick_refresh_total", &self.tick_refresh);
+        counter("auth_tick_rejected_total", &self.tick_rejected);
+        counter("auth_logout_total", &self.logout);
+
+        let _ = writeln!(out, "# TYPE auth_login_failure_total counter");
+        for (reason, count) in self.login_failure.lock().expect("metrics poisoned").iter() {
+            let _ = writeln!(
+                out,
+                "auth_login_failure_total{{reason=\"{reason}\"}} {count}"
+            );
+        }
+        out
+    }
+}
+
+#[get("/metrics")]
+pub async fn handler() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(ContentType::plaintext())
+        .body(METRICS.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_login_bumps_failure_counter() {
+        let metrics = AuthMetrics::default();
+        metrics.login_failed("invalid_credential");
+        metrics.login_failed("invalid_credential");
+        metrics.login_succeeded();
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("auth_login_failure_total{reason=\"invalid_credential\"} 2\n"));
+        assert!(rendered.contains("auth_login_success_total 1\n"));
+        assert!(rendered.contains("auth_logout_total 0\n"));
+    }
+}
@@ -1,35 +1,71 @@
+pub mod admin;
 pub mod auth;
+pub mod cors;
 pub mod leaderboard;
+pub mod metrics;
 pub mod ping;
+pub mod request_id;
 pub mod wheel;
 
-use crate::error::ServerError;
-use actix_cors::Cors;
-use actix_web::{App, HttpServer};
+use crate::{config::CONFIG, error::ServerError};
+use actix_web::{App, HttpServer, middleware::from_fn, web};
+use std::{sync::Arc, time::Duration};
+
+const SHUTDOWN_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub async fn run() -> Result<(), ServerError> {
-    HttpServer::new(|| {
-        let cors = Cors::default()
-            .allowed_origin("http://localhost:5173")
-            .allowed_origin("https://mercuryland.pp.ua")
-            .allowed_origin("https://www.mercuryland.pp.ua")
-            .allow_any_header()
-            .allowed_methods(vec!["GET", "POST"]);
+    CONFIG.cors.validate()?;
+    let session = web::Data::new(CONFIG.session.clone());
+    let revocations = web::Data::new(auth::revocation::RevocationStore::default());
+    let tracker = web::Data::new(auth::tracker::SessionTracker::default());
+    let lockout = web::Data::new(auth::lockout::LoginLockout::default());
+    let sinks = web::Data::new(auth::sink::LoginSinks::standard(&CONFIG.session));
+    let debounce = web::Data::new(auth::debounce::LoginDebounce::default());
+    let in_flight = web::Data::new(auth::inflight::InFlightLimit::new(
+        CONFIG.session.max_in_flight,
+    ));
+    let clock = auth::clock::data(Arc::new(auth::clock::SystemClock));
+    auth::prune::Maintenance::default()
+        .with(revocations.clone())
+        .with(tracker.clone())
+        .with(lockout.clone())
+        .with(debounce.clone())
+        .spawn(clock.clone(), session.clone());
 
+    HttpServer::new(move || {
         App::new()
-            .wrap(cors)
+            .wrap(cors::cors(&CONFIG.cors))
+            .wrap(from_fn(request_id::assign))
+            .app_data(session.clone())
+            .app_data(revocations.clone())
+            .app_data(clock.clone())
+            .app_data(tracker.clone())
+            .app_data(lockout.clone())
+            .app_data(sinks.clone())
+            .app_data(in_flight.clone())
+            .app_data(debounce.clone())
             .service(ping::handler)
-            .service(auth::login::login_handler)
-            .service(auth::login::logout_logging)
-            .service(auth::tick::handler)
+            .service(metrics::handler)
+            .configure(auth::configure)
             .service(wheel::create::handler)
             .service(wheel::update::handler)
             .service(wheel::submit::handler)
             .service(leaderboard::ldb::handler)
+            .configure(admin::configure)
     })
     .bind(("0.0.0.0", 8080))?
     .run()
     .await?;
 
+    // the server has stopped, give queued login records a chance to land
+    let drained = actix_web::rt::task::spawn_blocking(|| {
+        auth::login::drain_pending_records(SHUTDOWN_DRAIN_TIMEOUT)
+    })
+    .await
+    .unwrap_or(false);
+    if !drained {
+        log::error!("shutdown before all login events were recorded");
+    }
+
     Ok(())
 }
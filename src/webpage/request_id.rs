@@ -0,0 +1,122 @@
+use actix_web::{
+    Error, FromRequest, HttpMessage, HttpRequest,
+    body::MessageBody,
+    dev::{Payload, ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+};
+use std::future::{Ready, ready};
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: String;
+}
+
+/// the correlation id of the request being handled, for log lines.
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+impl FromRequest for RequestId {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let id = request.extensions().get::<RequestId>().cloned();
+        ready(Ok(id.unwrap_or_else(|| RequestId(String::from("unknown")))))
+    }
+}
+
+/// the id of the request whose handler is running, also for code without
+/// the request at hand.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+/// run `job` under the correlation id `id`, e.g. on a blocking thread.
+pub fn scope<R>(id: Option<String>, job: impl FnOnce() -> R) -> R {
+    match id {
+        Some(id) => CURRENT.sync_scope(id, job),
+        None => job(),
+    }
+}
+
+/// an incoming id is kept if it is short and plain, so it can be logged as is.
+fn incoming(request: &ServiceRequest) -> Option<String> {
+    let id = request.headers().get(REQUEST_ID)?.to_str().ok()?;
+    let plain = id
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    (plain && !id.is_empty() && id.len() <= MAX_LEN).then(|| id.to_string())
+}
+
+/// tag every request with a correlation id, taken from `X-Request-Id` or
+/// generated, and echo it back in the response.
+pub async fn assign(
+    request: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = incoming(&request).unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = CURRENT.scope(id.clone(), next.call(request)).await?;
+    response.headers_mut().insert(
+        REQUEST_ID,
+        HeaderValue::from_str(&id).expect("request id is plain ascii"),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{
+        App, HttpResponse, Responder, get,
+        middleware::from_fn,
+        test::{TestRequest, call_service, init_service, read_body},
+    };
+
+    #[get("/id")]
+    async fn echo(id: RequestId) -> impl Responder {
+        assert_eq!(current().as_deref(), Some(id.0.as_str()));
+        HttpResponse::Ok().body(id.0)
+    }
+
+    async fn request_id(header: Option<&str>) -> (String, String) {
+        let app = init_service(App::new().wrap(from_fn(assign)).service(echo)).await;
+        let mut request = TestRequest::get().uri("/id");
+        if let Some(header) = header {
+            request = request.insert_header((REQUEST_ID, header));
+        }
+        let response = call_service(&app, request.to_request()).await;
+        let header = response.headers().get(REQUEST_ID).unwrap();
+        let header = header.to_str().unwrap().to_string();
+        let body = read_body(response).await;
+        (header, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[actix_web::test]
+    async fn incoming_id_is_kept() {
+        let (header, body) = request_id(Some("abc-123")).await;
+        assert_eq!(header, "abc-123");
+        assert_eq!(body, "abc-123");
+    }
+
+    #[actix_web::test]
+    async fn missing_or_odd_id_is_generated() {
+        for incoming in [None, Some("has space"), Some("")] {
+            let (header, body) = request_id(incoming).await;
+            assert_eq!(header, body);
+            assert!(uuid::Uuid::parse_str(&header).is_ok());
+        }
+    }
+
+    #[test]
+    fn scope_sets_current() {
+        assert_eq!(current(), None);
+        let id = scope(Some(String::from("abc")), current);
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+}